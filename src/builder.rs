@@ -0,0 +1,320 @@
+//! This module is dedicated to the configuration of the [`Interpreter`]
+use rand::{
+    rngs::{OsRng, ThreadRng},
+    SeedableRng,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{DiceSource, Interpreter, Sequence, Value};
+
+/// The random number generator used when the interpreter is seeded.
+///
+/// Unlike [`ThreadRng`] its output only depends on the seed, and the faces
+/// are drawn from it the same way everywhere, see [`DiceSource`], so any roll
+/// can be reproduced from its seed.
+pub type SeededRng = rand_chacha::ChaCha8Rng;
+
+/// Everything that changes the way an [`Interpreter`] evaluates an
+/// expression, except for its source of randomness.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    pub limits: Limits,
+    pub extensions: Extensions,
+    pub rounding: Rounding,
+    pub verbosity: Verbosity,
+}
+
+/// Safety caps protecting the interpreter against expressions like
+/// `999999999d6` that would otherwise hang it or exhaust the memory.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Limits {
+    /// Maximum number of dice thrown by a single roll, before explosions.
+    pub dice: isize,
+    /// Maximum number of faces of a die.
+    pub faces: isize,
+    /// Maximum number of times a single die can explode in a row.
+    pub explosion_depth: usize,
+    /// Maximum number of operations and dice thrown for a whole expression.
+    pub steps: usize,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            dice: 1_000,
+            faces: 1_000_000,
+            explosion_depth: 100,
+            steps: 100_000,
+        }
+    }
+}
+
+impl Limits {
+    /// No limits at all, only use this with trusted input.
+    pub fn none() -> Self {
+        Self {
+            dice: isize::MAX,
+            faces: isize::MAX,
+            explosion_depth: usize::MAX,
+            steps: usize::MAX,
+        }
+    }
+}
+
+/// The parts of the dice notation going further than `NdM` and arithmetic.
+/// They're all enabled by default, evaluating an expression using a
+/// disabled extension fails.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Extensions {
+    /// Flag the critical dice, and the `cs` and `cf` modifiers.
+    pub critical: bool,
+    /// The `kh` and `kl` modifiers.
+    pub keep: bool,
+    /// The `!` modifier.
+    pub explode: bool,
+    /// The `adv` and `dis` shorthands.
+    pub advantage: bool,
+}
+
+impl Default for Extensions {
+    fn default() -> Self {
+        Self::all()
+    }
+}
+
+impl Extensions {
+    pub fn all() -> Self {
+        Self {
+            critical: true,
+            keep: true,
+            explode: true,
+            advantage: true,
+        }
+    }
+
+    pub fn none() -> Self {
+        Self {
+            critical: false,
+            keep: false,
+            explode: false,
+            advantage: false,
+        }
+    }
+}
+
+/// How the result of a division is rounded.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Rounding {
+    /// Toward zero, `-7 / 2` is `-3`.
+    #[default]
+    Truncate,
+    /// Toward negative infinity, `-7 / 2` is `-4`.
+    Floor,
+    /// Toward positive infinity, `7 / 2` is `4`.
+    Ceil,
+    /// To the nearest integer, half way cases away from zero, `7 / 2` is `4`.
+    Nearest,
+}
+
+impl Rounding {
+    /// Divide `left` by a `right` that must not be zero.
+    ///
+    /// # Panics
+    ///
+    /// When `right` is zero or the result overflows, like `isize::MIN / -1`.
+    pub fn divide(&self, left: isize, right: isize) -> isize {
+        self.checked_divide(left, right)
+            .expect("attempt to divide by zero or with overflow")
+    }
+
+    /// Divide `left` by `right`, `None` when `right` is zero or the result
+    /// overflows.
+    pub fn checked_divide(&self, left: isize, right: isize) -> Option<isize> {
+        let quotient = left.checked_div(right)?;
+        let remainder = left.checked_rem(right)?;
+        if remainder == 0 {
+            return Some(quotient);
+        }
+        // the sign of the exact result
+        let positive = (left < 0) == (right < 0);
+        // the quotient is at most `isize::MAX / 2` when there's a remainder
+        Some(match self {
+            Rounding::Truncate => quotient,
+            Rounding::Floor if positive => quotient,
+            Rounding::Floor => quotient - 1,
+            Rounding::Ceil if positive => quotient + 1,
+            Rounding::Ceil => quotient,
+            Rounding::Nearest if remainder.unsigned_abs() * 2 < right.unsigned_abs() => quotient,
+            Rounding::Nearest if positive => quotient + 1,
+            Rounding::Nearest => quotient - 1,
+        })
+    }
+}
+
+/// How much of the evaluation is kept in the returned [`crate::Value`].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Verbosity {
+    /// Only the total.
+    Total,
+    /// The total and the breakdown of every die rolled.
+    #[default]
+    Breakdown,
+}
+
+impl Verbosity {
+    /// Drop what shouldn't be kept of a value.
+    pub fn apply(&self, value: Value) -> Value {
+        match self {
+            Verbosity::Total => Value::direct(*value),
+            Verbosity::Breakdown => value,
+        }
+    }
+}
+
+pub struct InterpreterBuilder<Rng> {
+    rng: Rng,
+    config: Config,
+}
+
+impl Default for InterpreterBuilder<ThreadRng> {
+    fn default() -> Self {
+        Self {
+            rng: rand::thread_rng(),
+            config: Config::default(),
+        }
+    }
+}
+
+impl<R: DiceSource> InterpreterBuilder<R> {
+    /// Use another source for the faces of the dice, any [`rand::Rng`] or a
+    /// [`DiceSource`].
+    pub fn rng<R2: DiceSource>(self, rng: R2) -> InterpreterBuilder<R2> {
+        InterpreterBuilder {
+            rng,
+            config: self.config,
+        }
+    }
+
+    /// Use a [`SeededRng`], rolling the same dice every time the same seed
+    /// is used.
+    pub fn seed(self, seed: u64) -> InterpreterBuilder<SeededRng> {
+        self.rng(SeededRng::seed_from_u64(seed))
+    }
+
+    /// Roll these faces in order, whatever the dice are, to know the exact
+    /// result of an expression in the tests. See [`Sequence`].
+    pub fn faces(self, faces: impl IntoIterator<Item = isize>) -> InterpreterBuilder<Sequence> {
+        self.rng(Sequence::new(faces))
+    }
+
+    /// Use the randomness of the operating system, like `/dev/urandom`.
+    pub fn os_rng(self) -> InterpreterBuilder<OsRng> {
+        self.rng(OsRng)
+    }
+
+    pub fn config(mut self, config: Config) -> Self {
+        self.config = config;
+        self
+    }
+
+    pub fn limits(mut self, limits: Limits) -> Self {
+        self.config.limits = limits;
+        self
+    }
+
+    pub fn max_dice(mut self, dice: isize) -> Self {
+        self.config.limits.dice = dice;
+        self
+    }
+
+    pub fn max_faces(mut self, faces: isize) -> Self {
+        self.config.limits.faces = faces;
+        self
+    }
+
+    pub fn max_explosion_depth(mut self, depth: usize) -> Self {
+        self.config.limits.explosion_depth = depth;
+        self
+    }
+
+    pub fn max_steps(mut self, steps: usize) -> Self {
+        self.config.limits.steps = steps;
+        self
+    }
+
+    pub fn extensions(mut self, extensions: Extensions) -> Self {
+        self.config.extensions = extensions;
+        self
+    }
+
+    pub fn rounding(mut self, rounding: Rounding) -> Self {
+        self.config.rounding = rounding;
+        self
+    }
+
+    pub fn verbosity(mut self, verbosity: Verbosity) -> Self {
+        self.config.verbosity = verbosity;
+        self
+    }
+
+    pub fn build(self) -> Interpreter<R> {
+        Interpreter::with_config(self.rng, self.config)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rounding() {
+        let test_values = [
+            (7, 2, [3, 3, 4, 4]),
+            (-7, 2, [-3, -4, -3, -4]),
+            (7, -2, [-3, -4, -3, -4]),
+            (-7, -2, [3, 3, 4, 4]),
+            (5, 3, [1, 1, 2, 2]),
+            (4, 3, [1, 1, 2, 1]),
+            (-4, 3, [-1, -2, -1, -1]),
+            (6, 3, [2, 2, 2, 2]),
+        ];
+        let roundings = [
+            Rounding::Truncate,
+            Rounding::Floor,
+            Rounding::Ceil,
+            Rounding::Nearest,
+        ];
+
+        for (left, right, expected) in test_values {
+            for (rounding, expected) in roundings.iter().zip(expected) {
+                assert_eq!(
+                    rounding.divide(left, right),
+                    expected,
+                    "{left} / {right} with {rounding:?}"
+                );
+            }
+        }
+        assert_eq!(Rounding::Floor.checked_divide(1, 0), None);
+        assert_eq!(Rounding::Floor.checked_divide(isize::MIN, -1), None);
+        assert_eq!(
+            Rounding::Floor.checked_divide(isize::MIN, 2),
+            Some(isize::MIN / 2)
+        );
+    }
+
+    #[test]
+    fn test_seed() -> crate::Result<()> {
+        let expr = crate::Parser::new("10d20 + 3d6!").parse()?;
+        let mut left = Interpreter::builder().seed(42).build();
+        let mut right = Interpreter::builder().seed(42).build();
+
+        for _ in 0..10 {
+            let (left, right) = (left.interpret(&expr)?, right.interpret(&expr)?);
+            assert_eq!(left.to_string(), right.to_string());
+        }
+        Ok(())
+    }
+}
@@ -1,5 +1,5 @@
 use miette::{Diagnostic, SourceSpan};
-use std::io;
+use std::{io, path::PathBuf};
 use thiserror::Error;
 
 pub type Result<T> = std::result::Result<T, Error>;
@@ -18,12 +18,26 @@ pub enum Error {
 
 #[derive(Diagnostic, Error, Debug)]
 pub enum SetupError {
-    #[error("Usage {} [script]", std::env::args().nth(0).unwrap())]
-    Usage,
-    #[error("IO Error: ")]
+    #[error("IO Error")]
     Io(#[from] io::Error),
+    #[error("IO Error: {}", path.display())]
+    File { path: PathBuf, source: io::Error },
 }
 
+impl SetupError {
+    /// An error while reading or writing the file at `path`.
+    pub fn file(path: impl Into<PathBuf>, source: io::Error) -> Self {
+        SetupError::File {
+            path: path.into(),
+            source,
+        }
+    }
+}
+
+#[derive(Diagnostic, Error, Debug, Clone, Copy, PartialEq, Eq)]
+#[error("A server seed is 64 hexadecimal digits.")]
+pub struct InvalidServerSeed;
+
 #[derive(Diagnostic, Error, Debug)]
 #[error("Error: {message}")]
 pub struct ParserError {
@@ -41,5 +55,64 @@ impl ParserError {
     }
 }
 
-#[derive(Error, Debug)]
-pub enum InterpreterError {}
+#[derive(Diagnostic, Error, Debug)]
+pub enum InterpreterError {
+    #[error("Error: Cannot roll {quantity} dice at once, the limit is {limit}.")]
+    TooManyDice {
+        quantity: isize,
+        limit: isize,
+        #[label("Too many dice")]
+        span: SourceSpan,
+    },
+    #[error("Error: Cannot roll a die with {faces} faces, the limit is {limit}.")]
+    TooManyFaces {
+        faces: isize,
+        limit: isize,
+        #[label("Too many faces")]
+        span: SourceSpan,
+    },
+    #[error("Error: Cannot roll a die with {faces} faces.")]
+    NotEnoughFaces {
+        faces: isize,
+        #[label("A die needs at least one face")]
+        span: SourceSpan,
+    },
+    #[error("Error: A die exploded more than {limit} times in a row.")]
+    ExplosionTooDeep {
+        limit: usize,
+        #[label("Exploded too many times")]
+        span: SourceSpan,
+    },
+    #[error("Error: Division by zero.")]
+    DivisionByZero {
+        #[label("Dividing by zero here")]
+        span: SourceSpan,
+    },
+    #[error("Error: The result is too large.")]
+    Overflow {
+        #[label("Overflowed here")]
+        span: SourceSpan,
+    },
+    #[error("Error: The {name} extension is disabled.")]
+    DisabledExtension {
+        name: &'static str,
+        #[label("Disabled extension")]
+        span: SourceSpan,
+    },
+    #[error("Error: The expression took more than {limit} steps to evaluate.")]
+    TooManySteps {
+        limit: usize,
+        #[label("Gave up here")]
+        span: SourceSpan,
+    },
+}
+
+impl InterpreterError {
+    pub fn to_report(self, src: &str) -> String {
+        let src = if src.is_empty() { "\n" } else { src };
+        format!(
+            "{:?}",
+            miette::Report::from(self).with_source_code(src.to_string())
+        )
+    }
+}
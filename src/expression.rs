@@ -1,6 +1,14 @@
+use serde::{Deserialize, Serialize};
+
 use crate::{Token, Value};
 
-#[derive(Debug, PartialEq)]
+/// The syntax tree of an expression.
+///
+/// It borrows the source it was parsed from, but can be detached from it
+/// with [`Expr::into_owned`] to be stored or serialized. Deserializing an
+/// expression always produces an owned one.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
 pub enum Expr<'a> {
     Unary {
         operator: Token<'a>,
@@ -21,5 +29,131 @@ pub enum Expr<'a> {
         quantity: Box<Expr<'a>>,
         dice: Token<'a>,
         faces: Box<Expr<'a>>,
+        modifiers: Vec<Modifier<'a>>,
+    },
+}
+
+/// An expression that doesn't borrow its source anymore. It can be cached
+/// past the life of its source or sent to another thread, and still points
+/// to its source with the spans of its tokens.
+pub type OwnedExpr = Expr<'static>;
+
+/// A modifier written right after a roll, like the `cs>=19` in `1d20cs>=19`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Modifier<'a> {
+    /// Every die matching the condition is flagged as a critical success.
+    CriticalSuccess {
+        token: Token<'a>,
+        condition: Condition,
     },
+    /// Every die matching the condition is flagged as a critical failure.
+    CriticalFailure {
+        token: Token<'a>,
+        condition: Condition,
+    },
+    /// Only the `count` highest dice are kept, the others are dropped.
+    KeepHighest { token: Token<'a>, count: isize },
+    /// Only the `count` lowest dice are kept, the others are dropped.
+    KeepLowest { token: Token<'a>, count: isize },
+    /// Every die matching the condition, or the max face if there is no
+    /// condition, is thrown once more.
+    Explode {
+        token: Token<'a>,
+        condition: Option<Condition>,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Condition {
+    pub compare: Compare,
+    pub value: isize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Compare {
+    Equal,
+    Greater,
+    GreaterEqual,
+    Less,
+    LessEqual,
+}
+
+impl Expr<'_> {
+    pub fn into_owned(self) -> Expr<'static> {
+        match self {
+            Expr::Unary { operator, right } => Expr::Unary {
+                operator: operator.into_owned(),
+                right: Box::new(right.into_owned()),
+            },
+            Expr::Binary {
+                left,
+                operator,
+                right,
+            } => Expr::Binary {
+                left: Box::new(left.into_owned()),
+                operator: operator.into_owned(),
+                right: Box::new(right.into_owned()),
+            },
+            Expr::Grouping { expression } => Expr::Grouping {
+                expression: Box::new(expression.into_owned()),
+            },
+            Expr::Literal { value } => Expr::Literal { value },
+            Expr::Roll {
+                quantity,
+                dice,
+                faces,
+                modifiers,
+            } => Expr::Roll {
+                quantity: Box::new(quantity.into_owned()),
+                dice: dice.into_owned(),
+                faces: Box::new(faces.into_owned()),
+                modifiers: modifiers.into_iter().map(Modifier::into_owned).collect(),
+            },
+        }
+    }
+}
+
+impl Modifier<'_> {
+    pub fn into_owned(self) -> Modifier<'static> {
+        match self {
+            Modifier::CriticalSuccess { token, condition } => Modifier::CriticalSuccess {
+                token: token.into_owned(),
+                condition,
+            },
+            Modifier::CriticalFailure { token, condition } => Modifier::CriticalFailure {
+                token: token.into_owned(),
+                condition,
+            },
+            Modifier::KeepHighest { token, count } => Modifier::KeepHighest {
+                token: token.into_owned(),
+                count,
+            },
+            Modifier::KeepLowest { token, count } => Modifier::KeepLowest {
+                token: token.into_owned(),
+                count,
+            },
+            Modifier::Explode { token, condition } => Modifier::Explode {
+                token: token.into_owned(),
+                condition,
+            },
+        }
+    }
+}
+
+impl Condition {
+    pub fn new(compare: Compare, value: isize) -> Self {
+        Self { compare, value }
+    }
+
+    pub fn matches(&self, face: isize) -> bool {
+        match self.compare {
+            Compare::Equal => face == self.value,
+            Compare::Greater => face > self.value,
+            Compare::GreaterEqual => face >= self.value,
+            Compare::Less => face < self.value,
+            Compare::LessEqual => face <= self.value,
+        }
+    }
 }
@@ -0,0 +1,192 @@
+//! This module is dedicated to the rolls anyone can verify afterward, with a
+//! commit-reveal scheme:
+//!
+//! 1. the game master picks a random [`ServerSeed`] and publishes its
+//!    [`ServerSeed::commitment`] before the session,
+//! 2. the players pick a client seed, any string,
+//! 3. the dice are rolled by a [`FairRng`] derived from both seeds,
+//! 4. the game master reveals the server seed after the session, anyone can
+//!    check it against the commitment and roll the dice again.
+//!
+//! Since the server seed is committed before the client seed is known,
+//! neither side can choose the dice.
+use std::{fmt::Display, str::FromStr};
+
+use rand::{CryptoRng, RngCore};
+use sha2::{Digest, Sha256};
+
+use crate::InvalidServerSeed;
+
+/// The secret of the game master, 32 bytes written in hexadecimal.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ServerSeed(pub [u8; 32]);
+
+impl ServerSeed {
+    /// A new seed from the randomness of the operating system.
+    pub fn random() -> Self {
+        let mut seed = [0; 32];
+        rand::rngs::OsRng.fill_bytes(&mut seed);
+        Self(seed)
+    }
+
+    /// The SHA-256 of the seed in hexadecimal, published before the session.
+    pub fn commitment(&self) -> String {
+        hex(&Sha256::digest(self.0))
+    }
+}
+
+impl Display for ServerSeed {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", hex(&self.0))
+    }
+}
+
+impl FromStr for ServerSeed {
+    type Err = InvalidServerSeed;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        if s.len() != 64 || !s.is_ascii() {
+            return Err(InvalidServerSeed);
+        }
+        let mut seed = [0; 32];
+        for (byte, digits) in seed.iter_mut().zip(s.as_bytes().chunks(2)) {
+            let digits = std::str::from_utf8(digits).map_err(|_| InvalidServerSeed)?;
+            *byte = u8::from_str_radix(digits, 16).map_err(|_| InvalidServerSeed)?;
+        }
+        Ok(Self(seed))
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+/// A generator whose output only depends on a [`ServerSeed`], a client seed
+/// and a counter.
+///
+/// The output is made of blocks, the block `n` is the SHA-256 of the server
+/// seed, followed by the client seed in UTF-8, followed by `n` as 8 big
+/// endian bytes. The counter is the position in this stream, in 32 bits
+/// words, so a single roll can be verified from the counter before it.
+#[derive(Debug, Clone)]
+pub struct FairRng {
+    server_seed: ServerSeed,
+    client_seed: String,
+    word_pos: u64,
+    block: [u8; 32],
+}
+
+impl FairRng {
+    pub fn new(server_seed: ServerSeed, client_seed: &str) -> Self {
+        let mut rng = Self {
+            server_seed,
+            client_seed: client_seed.to_string(),
+            word_pos: 0,
+            block: [0; 32],
+        };
+        rng.refill();
+        rng
+    }
+
+    pub fn client_seed(&self) -> &str {
+        &self.client_seed
+    }
+
+    /// The number of 32 bits words generated so far.
+    pub fn counter(&self) -> u64 {
+        self.word_pos
+    }
+
+    /// Continue the stream from another position.
+    pub fn set_counter(&mut self, counter: u64) {
+        self.word_pos = counter;
+        self.refill();
+    }
+
+    /// Compute the block of the current position.
+    fn refill(&mut self) {
+        let mut hasher = Sha256::new();
+        hasher.update(self.server_seed.0);
+        hasher.update(self.client_seed.as_bytes());
+        hasher.update((self.word_pos / 8).to_be_bytes());
+        self.block = hasher.finalize().into();
+    }
+}
+
+impl RngCore for FairRng {
+    fn next_u32(&mut self) -> u32 {
+        let index = (self.word_pos % 8) as usize * 4;
+        let word = u32::from_le_bytes(self.block[index..index + 4].try_into().unwrap());
+        self.word_pos += 1;
+        if self.word_pos.is_multiple_of(8) {
+            self.refill();
+        }
+        word
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        let low = self.next_u32() as u64;
+        let high = self.next_u32() as u64;
+        (high << 32) | low
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        for chunk in dest.chunks_mut(4) {
+            let word = self.next_u32().to_le_bytes();
+            chunk.copy_from_slice(&word[..chunk.len()]);
+        }
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand::Error> {
+        self.fill_bytes(dest);
+        Ok(())
+    }
+}
+
+impl CryptoRng for FairRng {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Interpreter, Parser};
+
+    #[test]
+    fn test_server_seed() {
+        let seed: ServerSeed = "00".repeat(32).parse().unwrap();
+        assert_eq!(seed, ServerSeed([0; 32]));
+        assert_eq!(seed.to_string(), "00".repeat(32));
+        assert_eq!(
+            seed.commitment(),
+            "66687aadf862bd776c8fc18b8e9f8e20089714856ee233b3902a591d0d5f2925"
+        );
+        assert!("00".parse::<ServerSeed>().is_err());
+        assert!("zz".repeat(32).parse::<ServerSeed>().is_err());
+        assert!("é".repeat(32).parse::<ServerSeed>().is_err());
+
+        let seed = ServerSeed::random();
+        assert_eq!(seed.to_string().parse::<ServerSeed>(), Ok(seed));
+    }
+
+    #[test]
+    fn test_fair_rng() -> crate::Result<()> {
+        let seed = ServerSeed([7; 32]);
+        let mut rng = FairRng::new(seed, "players");
+        let words: Vec<_> = (0..20).map(|_| rng.next_u32()).collect();
+        assert_eq!(rng.counter(), 20);
+        rng.set_counter(13);
+        assert_eq!(rng.next_u32(), words[13]);
+        assert_ne!(FairRng::new(seed, "player").next_u32(), words[0]);
+
+        // a roll can be replayed from the counter before it
+        let expr = Parser::new("10d20 + 4d6kh3!").parse()?;
+        let mut interpreter = Interpreter::new(FairRng::new(seed, "players"));
+        interpreter.interpret(&expr)?;
+        let mut rng = FairRng::new(seed, "players");
+        rng.set_counter(interpreter.rng().counter());
+        let value = interpreter.interpret(&expr)?;
+        let replayed = Interpreter::new(rng).interpret(&expr)?;
+        assert_eq!(value.to_string(), replayed.to_string());
+        Ok(())
+    }
+}
@@ -1,10 +1,25 @@
-use crate::{Expr, InterpreterError, Kind, TokenType, Value};
-use rand::{rngs::ThreadRng, Rng};
+use crate::{
+    Compare, Condition, Config, Critical, DiceSource, Die, Expr, InterpreterBuilder,
+    InterpreterError, Kind, Limits, Modifier, Token, TokenType, Value,
+};
+use rand::rngs::ThreadRng;
 
 type Result<T> = std::result::Result<T, InterpreterError>;
 
+/// The value computed for an expression, along with the evaluation of each
+/// of its sub-expressions.
+#[derive(Debug, Clone)]
+pub struct Evaluation<'e, 'a> {
+    pub expr: &'e Expr<'a>,
+    pub value: Value,
+    /// In the order they appear in the source.
+    pub children: Vec<Evaluation<'e, 'a>>,
+}
+
 pub struct Interpreter<Rng> {
     rng: Rng,
+    config: Config,
+    steps: usize,
 }
 
 impl Interpreter<ThreadRng> {
@@ -14,11 +29,23 @@ impl Interpreter<ThreadRng> {
         let mut interpreter = Interpreter::<ThreadRng>::default();
         Ok(interpreter.interpret(&expr)?)
     }
+
+    pub fn builder() -> InterpreterBuilder<ThreadRng> {
+        InterpreterBuilder::default()
+    }
 }
 
-impl<R: Rng> Interpreter<R> {
+impl<R: DiceSource> Interpreter<R> {
     pub fn new(rng: R) -> Self {
-        Self { rng }
+        Self::with_config(rng, Config::default())
+    }
+
+    pub fn with_config(rng: R, config: Config) -> Self {
+        Self {
+            rng,
+            config,
+            steps: 0,
+        }
     }
 
     pub fn run_with_rng(source: &str, rng: R) -> crate::error::Result<Value> {
@@ -29,73 +56,279 @@ impl<R: Rng> Interpreter<R> {
     }
 
     pub fn interpret(&mut self, expression: &Expr<'_>) -> Result<Value> {
-        expression.interpret(self)
+        let value = self.evaluate(expression)?.value;
+        Ok(self.config.verbosity.apply(value))
+    }
+
+    /// Interpret the expression, keeping the value of every sub-expression.
+    pub fn evaluate<'e, 'a>(&mut self, expression: &'e Expr<'a>) -> Result<Evaluation<'e, 'a>> {
+        self.steps = 0;
+        expression.evaluate(self)
+    }
+
+    /// The source of randomness, to save its state.
+    pub fn rng(&self) -> &R {
+        &self.rng
+    }
+
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    pub fn limits(&self) -> &Limits {
+        &self.config.limits
+    }
+
+    fn extension(&self, enabled: bool, name: &'static str, token: &Token) -> Result<()> {
+        if enabled {
+            Ok(())
+        } else {
+            Err(InterpreterError::DisabledExtension {
+                name,
+                span: token.span.clone().into(),
+            })
+        }
+    }
+
+    /// Account for one more operation, failing once the limit is reached.
+    fn step(&mut self, token: &Token) -> Result<()> {
+        self.steps += 1;
+        if self.steps > self.config.limits.steps {
+            Err(InterpreterError::TooManySteps {
+                limit: self.config.limits.steps,
+                span: token.span.clone().into(),
+            })
+        } else {
+            Ok(())
+        }
+    }
+
+    fn roll(
+        &mut self,
+        quantity: isize,
+        dice: &Token,
+        faces: isize,
+        modifiers: &[Modifier],
+    ) -> Result<Vec<Die>> {
+        if quantity > self.config.limits.dice {
+            return Err(InterpreterError::TooManyDice {
+                quantity,
+                limit: self.config.limits.dice,
+                span: dice.span.clone().into(),
+            });
+        }
+        if faces < 1 {
+            return Err(InterpreterError::NotEnoughFaces {
+                faces,
+                span: dice.span.clone().into(),
+            });
+        }
+        if faces > self.config.limits.faces {
+            return Err(InterpreterError::TooManyFaces {
+                faces,
+                limit: self.config.limits.faces,
+                span: dice.span.clone().into(),
+            });
+        }
+
+        let extensions = self.config.extensions;
+        if matches!(dice.ty, TokenType::Advantage | TokenType::Disadvantage) {
+            self.extension(extensions.advantage, "advantage", dice)?;
+        }
+
+        // By default only the natural max and the natural 1 are critical
+        let mut success = extensions
+            .critical
+            .then_some(Condition::new(Compare::Equal, faces));
+        let mut failure = extensions
+            .critical
+            .then_some(Condition::new(Compare::Equal, 1));
+        let mut explode = None;
+        for modifier in modifiers {
+            match modifier {
+                Modifier::CriticalSuccess { token, condition } => {
+                    self.extension(extensions.critical, "critical", token)?;
+                    success = Some(*condition);
+                }
+                Modifier::CriticalFailure { token, condition } => {
+                    self.extension(extensions.critical, "critical", token)?;
+                    failure = Some(*condition);
+                }
+                Modifier::Explode { token, condition } => {
+                    self.extension(extensions.explode, "explode", token)?;
+                    explode = Some(condition.unwrap_or(Condition::new(Compare::Equal, faces)))
+                }
+                Modifier::KeepHighest { token, .. } | Modifier::KeepLowest { token, .. } => {
+                    // `adv` and `dis` are made of a keep modifier
+                    if !matches!(token.ty, TokenType::Advantage | TokenType::Disadvantage) {
+                        self.extension(extensions.keep, "keep", token)?;
+                    }
+                }
+            }
+        }
+
+        let mut results = Vec::new();
+        for _ in 0..quantity {
+            let mut depth = 0;
+            loop {
+                self.step(dice)?;
+                let value = self.rng.roll(faces);
+                let critical = if success.is_some_and(|success| success.matches(value)) {
+                    Some(Critical::Success)
+                } else if failure.is_some_and(|failure| failure.matches(value)) {
+                    Some(Critical::Failure)
+                } else {
+                    None
+                };
+                let exploded = explode.is_some_and(|explode| explode.matches(value));
+                results.push(Die {
+                    value,
+                    faces,
+                    critical,
+                    dropped: false,
+                    exploded,
+                });
+
+                if !exploded {
+                    break;
+                }
+                depth += 1;
+                if depth > self.config.limits.explosion_depth {
+                    return Err(InterpreterError::ExplosionTooDeep {
+                        limit: self.config.limits.explosion_depth,
+                        span: dice.span.clone().into(),
+                    });
+                }
+            }
+        }
+
+        for modifier in modifiers {
+            match modifier {
+                Modifier::KeepHighest { count, .. } => keep(&mut results, *count, true),
+                Modifier::KeepLowest { count, .. } => keep(&mut results, *count, false),
+                Modifier::CriticalSuccess { .. }
+                | Modifier::CriticalFailure { .. }
+                | Modifier::Explode { .. } => (),
+            }
+        }
+
+        Ok(results)
     }
 }
 
 impl Default for Interpreter<ThreadRng> {
     fn default() -> Self {
-        Self {
-            rng: rand::thread_rng(),
-        }
+        Self::new(rand::thread_rng())
     }
 }
 
-impl Expr<'_> {
-    fn interpret<R: Rng>(&self, interpreter: &mut Interpreter<R>) -> Result<Value> {
-        match self {
+impl<'a> Expr<'a> {
+    fn evaluate<'e, R: DiceSource>(
+        &'e self,
+        interpreter: &mut Interpreter<R>,
+    ) -> Result<Evaluation<'e, 'a>> {
+        let (value, children) = match self {
             Expr::Unary { operator, right } => {
-                let right = right.interpret(interpreter)?;
-                match operator.lexeme() {
-                    "-" => Ok(-right),
+                interpreter.step(operator)?;
+                let right = right.evaluate(interpreter)?;
+                let value = match operator.ty {
+                    TokenType::Minus => right.value.clone().checked_neg(),
                     _ => unreachable!(),
-                }
+                };
+                (value.ok_or_else(|| overflow(operator))?, vec![right])
             }
             Expr::Binary {
                 left,
                 operator,
                 right,
             } => {
-                let (left, right) = (left.interpret(interpreter)?, right.interpret(interpreter)?);
-                match operator.ty {
-                    TokenType::Plus => Ok(left + right),
-                    TokenType::Minus => Ok(left - right),
-                    TokenType::Star | TokenType::Multiplication => Ok(left * right),
-                    TokenType::Slash | TokenType::Division => Ok(left / right),
+                interpreter.step(operator)?;
+                let (left, right) = (left.evaluate(interpreter)?, right.evaluate(interpreter)?);
+                let (lhs, rhs) = (left.value.clone(), right.value.clone());
+                let value = match operator.ty {
+                    TokenType::Plus => lhs.checked_add(rhs),
+                    TokenType::Minus => lhs.checked_sub(rhs),
+                    TokenType::Star | TokenType::Multiplication => lhs.checked_mul(rhs),
+                    TokenType::Slash | TokenType::Division if *rhs == 0 => {
+                        return Err(InterpreterError::DivisionByZero {
+                            span: operator.span.clone().into(),
+                        })
+                    }
+                    TokenType::Slash | TokenType::Division => {
+                        lhs.checked_divide(rhs, interpreter.config.rounding)
+                    }
                     _ => unreachable!(),
-                }
+                };
+                (value.ok_or_else(|| overflow(operator))?, vec![left, right])
+            }
+            Expr::Grouping { expression } => {
+                let expression = expression.evaluate(interpreter)?;
+                (expression.value.clone(), vec![expression])
             }
-            Expr::Grouping { expression } => expression.interpret(interpreter),
-            Expr::Literal { value } => Ok(value.clone()),
+            Expr::Literal { value } => (value.clone(), Vec::new()),
             Expr::Roll {
                 quantity,
-                dice: _dice,
+                dice,
                 faces,
+                modifiers,
             } => {
-                let quantity = quantity.interpret(interpreter)?;
-                let faces = faces.interpret(interpreter)?;
+                let quantity = quantity.evaluate(interpreter)?;
+                let faces = faces.evaluate(interpreter)?;
 
-                let results: Vec<isize> = (0..*quantity)
-                    .map(|_| interpreter.rng.gen_range(1..=*faces))
-                    .collect();
-                let value = results.iter().sum();
+                let results = interpreter.roll(*quantity.value, dice, *faces.value, modifiers)?;
+                let value = results
+                    .iter()
+                    .filter(|die| !die.dropped)
+                    .try_fold(0isize, |sum, die| sum.checked_add(die.value))
+                    .ok_or_else(|| overflow(dice))?;
 
                 let all = Kind::Roll(
                     results
                         .into_iter()
-                        .map(|i| Kind::Direct(i))
+                        .map(Kind::Die)
                         .intersperse(Kind::Token("+".to_string()))
                         .collect(),
                 );
-                Ok(Value::new(value, vec![Kind::Direct(value), all]))
+                (
+                    Value::new(value, vec![Kind::Direct(value), all]),
+                    vec![quantity, faces],
+                )
             }
-        }
+        };
+
+        Ok(Evaluation {
+            expr: self,
+            value,
+            children,
+        })
+    }
+}
+
+fn overflow(token: &Token) -> InterpreterError {
+    InterpreterError::Overflow {
+        span: token.span.clone().into(),
+    }
+}
+
+/// Drop every die that is not one of the `count` highest (or lowest) dice
+/// still in play. Ties are broken in favor of the first dice rolled.
+fn keep(dice: &mut [Die], count: isize, highest: bool) {
+    let mut kept: Vec<&mut Die> = dice.iter_mut().filter(|die| !die.dropped).collect();
+    if highest {
+        kept.sort_by_key(|die| std::cmp::Reverse(die.value));
+    } else {
+        kept.sort_by_key(|die| die.value);
     }
+    kept.into_iter()
+        .skip(count.max(0) as usize)
+        .for_each(|die| die.dropped = true);
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::{Error, Interpreter};
+    use crate::{
+        Error, Extensions, Interpreter, InterpreterError, Limits, Parser, Rounding, Verbosity,
+    };
 
     #[test]
     fn test_value() -> Result<(), Error> {
@@ -135,4 +368,260 @@ mod tests {
         }
         Ok(())
     }
+
+    #[test]
+    fn test_critical() -> Result<(), Error> {
+        let res = Interpreter::run("3d20cs>=1")?;
+        assert_eq!(res.dice().len(), 3);
+        assert!(res.has_critical_success());
+        assert!(!res.has_critical_failure());
+
+        let res = Interpreter::run("2d20cs>20cf<=20")?;
+        assert!(!res.has_critical_success());
+        assert!(res.has_critical_failure());
+
+        // a natural max is a critical success by default
+        let res = Interpreter::run("1d1")?;
+        assert!(res.has_critical_success());
+        assert_eq!(res.to_string(), "1 <= (1 (*1*))");
+
+        let res = Interpreter::run("1 + 2")?;
+        assert!(res.dice().is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_keep() -> Result<(), Error> {
+        let res = Interpreter::run("4d6kh3")?;
+        let dice = res.dice();
+        assert_eq!(dice.len(), 4);
+        assert_eq!(dice.iter().filter(|die| die.dropped).count(), 1);
+        let lowest = dice.iter().map(|die| die.value).min().unwrap();
+        assert_eq!(
+            *res,
+            dice.iter().map(|die| die.value).sum::<isize>() - lowest
+        );
+
+        let res = Interpreter::run("4d6kl1")?;
+        let lowest = res.dice().iter().map(|die| die.value).min().unwrap();
+        assert_eq!(res, lowest);
+
+        let res = Interpreter::run("3d6kh5")?;
+        assert!(res.dice().iter().all(|die| !die.dropped));
+        Ok(())
+    }
+
+    #[test]
+    fn test_advantage() -> Result<(), Error> {
+        let res = Interpreter::run("adv")?;
+        let dice = res.dice();
+        assert_eq!(dice.len(), 2);
+        assert!(dice.iter().all(|die| die.faces == 20));
+        assert_eq!(dice.iter().filter(|die| die.dropped).count(), 1);
+        assert_eq!(res, dice.iter().map(|die| die.value).max().unwrap());
+
+        let res = Interpreter::run("dis + 3")?;
+        let lowest = res.dice().iter().map(|die| die.value).min().unwrap();
+        assert_eq!(res, lowest + 3);
+
+        // the dropped die is not counted as a critical
+        let res = Interpreter::run("dis cs>=1")?;
+        assert!(res.has_critical_success());
+        assert_eq!(
+            res.dice()
+                .iter()
+                .filter(|die| die.is_critical_success())
+                .count(),
+            1
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_explode() -> Result<(), Error> {
+        let res = Interpreter::run("10d6!")?;
+        let dice = res.dice();
+        assert_eq!(dice.iter().filter(|die| !die.exploded).count(), 10);
+        assert!(dice
+            .iter()
+            .filter(|die| die.exploded)
+            .all(|die| die.value == 6));
+
+        let res = Interpreter::run("3d6!>7")?;
+        assert_eq!(res.dice().len(), 3);
+        Ok(())
+    }
+
+    #[test]
+    fn test_exact_rolls() -> Result<(), Error> {
+        let test_values = [
+            ("3d6", vec![3, 5, 6], "14 <= (14 (3 + 5 + *6*))"),
+            (
+                "3d6kh2!",
+                vec![6, 2, 6, 4, 1],
+                "12 <= (12 (*6*! + ~2~ + *6*! + ~4~ + ~1~))",
+            ),
+            (
+                "4d6kl1 + 2",
+                vec![3, 1, 5, 2],
+                "3 <= (1 (~3~ + _1_ + ~5~ + ~2~) + 2)",
+            ),
+            (
+                "1d20cs>=19 - 1d20cf<=2",
+                vec![19, 2],
+                "17 <= (19 (*19*) - 2 (_2_))",
+            ),
+            ("adv", vec![15, 7], "15 <= (15 (15 + ~7~))"),
+            ("dis", vec![15, 7], "7 <= (7 (~15~ + 7))"),
+            (
+                "2d10!>=9",
+                vec![9, 10, 3, 1],
+                "23 <= (23 (9! + *10*! + 3 + _1_))",
+            ),
+            ("(2d4 + 2) / 2", vec![1, 4], "3 <= (5 (_1_ + *4*) + 2 ÷ 2)"),
+        ];
+
+        for (input, faces, output) in test_values {
+            let expr = Parser::new(input).parse()?;
+            let mut interpreter = Interpreter::builder().faces(faces).build();
+            let res = interpreter.interpret(&expr)?;
+            assert_eq!(res.to_string(), output, "{input}");
+            assert_eq!(interpreter.rng().remaining().count(), 0, "{input}");
+        }
+
+        let expr = Parser::new("1d6!").parse()?;
+        let res = Interpreter::builder()
+            .faces([6, 6, 6])
+            .max_explosion_depth(2)
+            .build()
+            .interpret(&expr);
+        assert!(matches!(
+            res,
+            Err(InterpreterError::ExplosionTooDeep { limit: 2, .. })
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_limits() -> Result<(), Error> {
+        let mut interpreter = Interpreter::builder()
+            .max_dice(10)
+            .max_faces(100)
+            .max_explosion_depth(5)
+            .build();
+
+        let expr = Parser::new("11d6").parse()?;
+        let res = interpreter.interpret(&expr);
+        assert!(matches!(
+            res,
+            Err(InterpreterError::TooManyDice { quantity: 11, limit: 10, span }) if span == (2..3).into()
+        ));
+
+        let expr = Parser::new("1 + 1d101").parse()?;
+        let res = interpreter.interpret(&expr);
+        assert!(matches!(
+            res,
+            Err(InterpreterError::TooManyFaces { faces: 101, limit: 100, span }) if span == (5..6).into()
+        ));
+
+        let expr = Parser::new("1d0").parse()?;
+        let res = interpreter.interpret(&expr);
+        assert!(matches!(
+            res,
+            Err(InterpreterError::NotEnoughFaces { faces: 0, .. })
+        ));
+
+        let expr = Parser::new("1d6!>=1").parse()?;
+        let res = interpreter.interpret(&expr);
+        assert!(matches!(
+            res,
+            Err(InterpreterError::ExplosionTooDeep { limit: 5, .. })
+        ));
+
+        let mut interpreter = Interpreter::builder().max_steps(10).build();
+        let expr = Parser::new("5d6 + 5d6").parse()?;
+        let res = interpreter.interpret(&expr);
+        assert!(matches!(
+            res,
+            Err(InterpreterError::TooManySteps { limit: 10, .. })
+        ));
+        // the steps are counted per expression
+        let expr = Parser::new("4d6 + 5d6").parse()?;
+        interpreter.interpret(&expr)?;
+        interpreter.interpret(&expr)?;
+
+        let expr = Parser::new("1000000 * 1000000 * 1000000 * 1000000").parse()?;
+        let res = interpreter.interpret(&expr);
+        assert!(matches!(
+            res,
+            Err(InterpreterError::Overflow { span }) if span == (28..29).into()
+        ));
+        for input in [
+            "-(0 - 9223372036854775807 - 1)",
+            "(0 - 9223372036854775807 - 1) / -1",
+        ] {
+            let expr = Parser::new(input).parse()?;
+            let res = interpreter.interpret(&expr);
+            assert!(
+                matches!(res, Err(InterpreterError::Overflow { .. })),
+                "{input}"
+            );
+        }
+
+        let mut interpreter = Interpreter::builder().limits(Limits::none()).build();
+        let expr = Parser::new("2000d6").parse()?;
+        interpreter.interpret(&expr)?;
+        assert!(Interpreter::run("2000d6").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_configuration() -> Result<(), Error> {
+        let mut interpreter = Interpreter::builder()
+            .rounding(Rounding::Ceil)
+            .verbosity(Verbosity::Total)
+            .build();
+        let expr = Parser::new("7 / 2 + 1d1").parse()?;
+        let res = interpreter.interpret(&expr)?;
+        assert_eq!(res, 5);
+        assert_eq!(res.to_string(), "5 <= (5)");
+
+        let expr = Parser::new("1 / (1 - 1)").parse()?;
+        let res = interpreter.interpret(&expr);
+        assert!(
+            matches!(res, Err(InterpreterError::DivisionByZero { span }) if span == (2..3).into())
+        );
+
+        let mut interpreter = Interpreter::builder()
+            .extensions(Extensions {
+                keep: false,
+                ..Extensions::all()
+            })
+            .build();
+        let expr = Parser::new("4d6kh3").parse()?;
+        let res = interpreter.interpret(&expr);
+        assert!(matches!(
+            res,
+            Err(InterpreterError::DisabledExtension { name: "keep", span }) if span == (3..5).into()
+        ));
+        let expr = Parser::new("adv").parse()?;
+        interpreter.interpret(&expr)?;
+
+        let mut interpreter = Interpreter::builder()
+            .extensions(Extensions::none())
+            .build();
+        let expr = Parser::new("adv").parse()?;
+        let res = interpreter.interpret(&expr);
+        assert!(matches!(
+            res,
+            Err(InterpreterError::DisabledExtension {
+                name: "advantage",
+                ..
+            })
+        ));
+        let expr = Parser::new("1d1").parse()?;
+        let res = interpreter.interpret(&expr)?;
+        assert!(!res.has_critical_success());
+        Ok(())
+    }
 }
@@ -18,5 +18,20 @@ pub use printer::*;
 mod interpreter;
 pub use interpreter::*;
 
+mod builder;
+pub use builder::*;
+
 mod value;
 pub use value::*;
+
+mod stats;
+pub use stats::*;
+
+mod log;
+pub use log::*;
+
+mod fair;
+pub use fair::*;
+
+mod randomness;
+pub use randomness::*;
@@ -0,0 +1,228 @@
+//! This module is dedicated to the logs of the rolls, proving that they
+//! weren't fudged. The fair rolls, see [`crate::FairRng`], can only be
+//! replayed once the server seed is revealed.
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use rand::SeedableRng;
+use serde::{Deserialize, Serialize};
+
+use crate::{Config, FairRng, Interpreter, Parser, SeededRng, ServerSeed, Value};
+
+/// A roll of a session, with everything needed to roll it again and check
+/// that the same dice come up. The logs are written as JSON lines.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(try_from = "RawLogEntry")]
+pub struct LogEntry {
+    /// The number of seconds since the Unix epoch when the roll happened.
+    pub timestamp: u64,
+    pub source: String,
+    /// The state of the generator before the roll.
+    #[serde(flatten)]
+    pub position: Position,
+    /// The configuration of the interpreter, the default one in the entries
+    /// written without it.
+    pub config: Config,
+    /// The result, with its breakdown.
+    pub result: Value,
+}
+
+/// Where a generator was in its stream before a roll.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(untagged)]
+pub enum Position {
+    /// A [`SeededRng`] and its position, see `ChaCha8Rng::get_word_pos`.
+    Seeded { seed: u64, word_pos: u128 },
+    /// A [`FairRng`] and its counter, the server seed isn't logged.
+    Fair { client_seed: String, counter: u64 },
+}
+
+/// An entry as it's written, since serde can't deserialize the `u128` of a
+/// flattened [`Position`].
+#[derive(Deserialize)]
+struct RawLogEntry {
+    timestamp: u64,
+    source: String,
+    seed: Option<u64>,
+    word_pos: Option<u128>,
+    client_seed: Option<String>,
+    counter: Option<u64>,
+    #[serde(default)]
+    config: Config,
+    result: Value,
+}
+
+impl TryFrom<RawLogEntry> for LogEntry {
+    type Error = &'static str;
+
+    fn try_from(raw: RawLogEntry) -> Result<Self, Self::Error> {
+        let position = match (raw.seed, raw.word_pos, raw.client_seed, raw.counter) {
+            (Some(seed), Some(word_pos), None, None) => Position::Seeded { seed, word_pos },
+            (None, None, Some(client_seed), Some(counter)) => Position::Fair {
+                client_seed,
+                counter,
+            },
+            _ => return Err("expected a seed and a word_pos, or a client_seed and a counter"),
+        };
+        Ok(Self {
+            timestamp: raw.timestamp,
+            source: raw.source,
+            position,
+            config: raw.config,
+            result: raw.result,
+        })
+    }
+}
+
+impl LogEntry {
+    /// An entry for a roll that just happened.
+    pub fn new(source: &str, position: Position, config: Config, result: Value) -> Self {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |duration| duration.as_secs());
+        Self {
+            timestamp,
+            source: source.to_string(),
+            position,
+            config,
+            result,
+        }
+    }
+
+    /// Roll the source again, with the generator in the same state and the
+    /// same configuration. The fair rolls need the server seed.
+    pub fn replay(&self, server_seed: Option<ServerSeed>) -> crate::Result<Value> {
+        Ok(self.replay_next(server_seed)?.0)
+    }
+
+    /// Like [`LogEntry::replay`], also returning the position of the
+    /// generator after the roll, where the next roll of the session starts.
+    pub fn replay_next(&self, server_seed: Option<ServerSeed>) -> crate::Result<(Value, Position)> {
+        let expr = Parser::new(&self.source).parse()?;
+        let replayed = match &self.position {
+            Position::Seeded { seed, word_pos } => {
+                let mut rng = SeededRng::seed_from_u64(*seed);
+                rng.set_word_pos(*word_pos);
+                let mut interpreter = Interpreter::with_config(rng, self.config);
+                let value = interpreter.evaluate(&expr)?.value;
+                let next = Position::Seeded {
+                    seed: *seed,
+                    word_pos: interpreter.rng().get_word_pos(),
+                };
+                (value, next)
+            }
+            Position::Fair {
+                client_seed,
+                counter,
+            } => {
+                let server_seed = server_seed.ok_or_else(|| {
+                    anyhow::anyhow!("The server seed is needed to replay a fair roll.")
+                })?;
+                let mut rng = FairRng::new(server_seed, client_seed);
+                rng.set_counter(*counter);
+                let mut interpreter = Interpreter::with_config(rng, self.config);
+                let value = interpreter.evaluate(&expr)?.value;
+                let next = Position::Fair {
+                    client_seed: client_seed.clone(),
+                    counter: interpreter.rng().counter(),
+                };
+                (value, next)
+            }
+        };
+        Ok(replayed)
+    }
+
+    /// Whether `value` has the same total and the same dice as the logged
+    /// result.
+    pub fn matches(&self, value: &Value) -> bool {
+        value.current == self.result.current && value.all == self.result.all
+    }
+
+    /// Whether replaying the roll gives the same total and the same dice.
+    pub fn verify(&self, server_seed: Option<ServerSeed>) -> crate::Result<bool> {
+        Ok(self.matches(&self.replay(server_seed)?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_replay() -> crate::Result<()> {
+        let config = Config {
+            rounding: crate::Rounding::Ceil,
+            extensions: crate::Extensions {
+                critical: false,
+                ..crate::Extensions::all()
+            },
+            ..Config::default()
+        };
+        let mut interpreter = Interpreter::builder().seed(7).config(config).build();
+        let source = "4d6kh3 / 2 + 1d20! + 1d1";
+        let expr = Parser::new(source).parse()?;
+        let mut entries = Vec::new();
+        for _ in 0..3 {
+            let position = Position::Seeded {
+                seed: 7,
+                word_pos: interpreter.rng().get_word_pos(),
+            };
+            let value = interpreter.evaluate(&expr)?.value;
+            entries.push(LogEntry::new(source, position, config, value));
+        }
+
+        for entry in &entries {
+            let json = serde_json::to_string(entry).unwrap();
+            let entry: LogEntry = serde_json::from_str(&json).unwrap();
+            assert!(entry.verify(None)?, "{json}");
+        }
+
+        // the entries written without a configuration use the default one
+        let mut json = serde_json::to_value(&entries[0]).unwrap();
+        json.as_object_mut().unwrap().remove("config");
+        let entry: LogEntry = serde_json::from_value(json).unwrap();
+        assert_eq!(entry.config, Config::default());
+        // where the 1d1 is a critical success
+        assert!(!entry.verify(None)?);
+
+        let mut fudged = entries[1].clone();
+        fudged.result = Value::direct(fudged.result.current);
+        assert!(!fudged.verify(None)?);
+        fudged.result = entries[0].result.clone();
+        assert!(!fudged.verify(None)?);
+        Ok(())
+    }
+
+    #[test]
+    fn test_replay_fair() -> crate::Result<()> {
+        let server_seed = ServerSeed([3; 32]);
+        let mut interpreter = Interpreter::new(FairRng::new(server_seed, "players"));
+        let expr = Parser::new("3d6!").parse()?;
+        let mut entries = Vec::new();
+        for _ in 0..3 {
+            let position = Position::Fair {
+                client_seed: "players".to_string(),
+                counter: interpreter.rng().counter(),
+            };
+            let value = interpreter.evaluate(&expr)?.value;
+            entries.push(LogEntry::new("3d6!", position, Config::default(), value));
+        }
+
+        for (entry, following) in entries.iter().zip(&entries[1..]) {
+            let (_, next) = entry.replay_next(Some(server_seed))?;
+            assert_eq!(next, following.position);
+        }
+        for entry in &entries {
+            let json = serde_json::to_string(entry).unwrap();
+            assert!(json.contains(r#""client_seed":"players""#), "{json}");
+            let deserialized: LogEntry = serde_json::from_str(&json).unwrap();
+            assert_eq!(deserialized.position, entry.position);
+            assert!(deserialized.verify(Some(server_seed))?, "{json}");
+        }
+        assert!(entries[2].verify(None).is_err());
+        let mut json = serde_json::to_value(&entries[2]).unwrap();
+        json.as_object_mut().unwrap().remove("counter");
+        assert!(serde_json::from_value::<LogEntry>(json).is_err());
+        assert!(!entries[2].verify(Some(ServerSeed([4; 32])))?);
+        Ok(())
+    }
+}
@@ -1,56 +1,656 @@
 use std::{
-    io::{BufRead, Write},
-    path::Path,
+    fs::OpenOptions,
+    io::BufRead,
+    path::{Path, PathBuf},
+    process::ExitCode,
 };
 
+use clap::{CommandFactory, Parser as _};
 use dicey::*;
+use rand::rngs::OsRng;
 
-fn main() -> Result<()> {
-    let args: Vec<_> = std::env::args().collect();
+mod repl;
+mod session;
 
-    if args.len() > 3 {
-        return Err(SetupError::Usage)?;
+use session::{Session, SessionRng};
+
+/// Can roll dice if you ask politely
+#[derive(clap::Parser, Clone, Debug)]
+#[clap(
+    version,
+    about,
+    after_help = "\
+EXIT STATUS:
+    0    Success
+    1    Unexpected error
+    2    Invalid arguments
+    3    An expression doesn't parse
+    4    An expression failed to evaluate, like `1d0`
+    5    A file can't be read or written
+    6    A roll of the log is invalid or doesn't match its replay, or the
+         server seed doesn't match its commitment"
+)]
+struct Cli {
+    #[clap(subcommand)]
+    command: Option<Command>,
+
+    /// Evaluate a script. Without a script or expressions, the expressions
+    /// are read from stdin one per line, or a prompt is started if stdin is
+    /// a terminal.
+    #[clap(short = 'f', long = "file", value_name = "SCRIPT")]
+    script: Option<PathBuf>,
+
+    /// Evaluate an expression instead of a script, can be repeated.
+    #[clap(
+        short = 'e',
+        long = "expr",
+        value_name = "EXPRESSION",
+        allow_hyphen_values = true,
+        conflicts_with = "script"
+    )]
+    expressions: Vec<String>,
+
+    /// Keep evaluating the next expressions after an error. The exit status
+    /// is the one of the last error.
+    #[clap(long, global = true)]
+    continue_on_error: bool,
+
+    /// Append every roll to this log, as JSON lines, to replay them later.
+    #[clap(long, global = true, value_name = "FILE")]
+    log: Option<PathBuf>,
+
+    /// Roll fair dice, derived from this server seed and the client seed.
+    /// Anyone can verify them once the server seed is revealed, see the
+    /// `commit` and `verify` commands.
+    #[clap(long, global = true, value_name = "HEX", conflicts_with = "seed")]
+    server_seed: Option<ServerSeed>,
+
+    /// The seed of the players for fair rolls, any string.
+    #[clap(long, global = true, value_name = "STRING", requires = "server-seed")]
+    client_seed: Option<String>,
+
+    /// Roll with the randomness of the operating system, like
+    /// `/dev/urandom`, instead of a seeded generator. The rolls can't be
+    /// replayed.
+    #[clap(long, global = true, conflicts_with_all = &["seed", "server-seed", "log"])]
+    os_random: bool,
+
+    /// Force the faces of the first dice rolled, for demonstrations, like
+    /// `--force 20,1`. A face larger than its die is its largest face.
+    #[clap(
+        long,
+        global = true,
+        value_name = "FACES",
+        value_delimiter = ',',
+        allow_hyphen_values = true,
+        conflicts_with_all = &["server-seed", "log"]
+    )]
+    force: Vec<isize>,
+
+    /// Seed the dice to replay a previous session.
+    #[clap(long, global = true)]
+    seed: Option<u64>,
+
+    /// Only print the total of every expression.
+    #[clap(short, long, global = true, conflicts_with = "verbose")]
+    quiet: bool,
+
+    /// Print the seed used for the session and the value of every
+    /// sub-expression before its result.
+    #[clap(short, long, global = true)]
+    verbose: bool,
+
+    /// Print every result as a JSON document containing all the dice rolled,
+    /// like `--format json`.
+    #[clap(long, global = true)]
+    json: bool,
+
+    /// The format of the results. Defaults to `ansi` when the output is
+    /// colored, and `plain` otherwise.
+    #[clap(long, global = true, value_enum, value_name = "FORMAT")]
+    format: Option<ResultFormat>,
+
+    /// Print the evaluated syntax tree in the graphviz format instead of the
+    /// result, showing the value of every node and the dice rolled.
+    #[clap(long, global = true)]
+    graph: bool,
+
+    /// Print the syntax tree instead of the result.
+    #[clap(
+        long,
+        global = true,
+        value_enum,
+        value_name = "FORMAT",
+        min_values = 0,
+        require_equals = true,
+        default_missing_value = "ascii"
+    )]
+    tree: Option<TreeFormat>,
+
+    /// When to color the output. `auto` colors it when printing to a
+    /// terminal, unless the `NO_COLOR` environment variable is set.
+    #[clap(
+        long,
+        global = true,
+        value_enum,
+        value_name = "WHEN",
+        default_value = "auto"
+    )]
+    color: ColorChoice,
+}
+
+#[derive(clap::Subcommand, Clone, Debug)]
+enum Command {
+    /// Roll the given expressions, like `dicey roll "2d6 + 3"`.
+    // the expressions can start with a `-`, unlike the options
+    #[clap(allow_hyphen_values = true)]
+    Roll {
+        #[clap(value_name = "EXPRESSION", required = true)]
+        expressions: Vec<String>,
+    },
+    /// Roll an expression many times and print the distribution of its
+    /// totals.
+    Stats {
+        expression: String,
+        /// How many times the expression is rolled.
+        #[clap(short = 'n', long, default_value = "10000")]
+        samples: usize,
+    },
+    /// Check that the scripts parse, without rolling anything.
+    Check {
+        #[clap(required = true)]
+        scripts: Vec<PathBuf>,
+    },
+    /// Print a script in its canonical form.
+    Fmt {
+        script: PathBuf,
+        /// Overwrite the script instead of printing it.
+        #[clap(short, long)]
+        write: bool,
+    },
+    /// Roll again every entry of a log written with `--log`, and check that
+    /// the same dice come up. The fair rolls need the `--server-seed`.
+    Replay { log: PathBuf },
+    /// Generate a server seed for fair rolls, and its commitment. Publish
+    /// the commitment before the session and keep the seed secret until the
+    /// end of the session.
+    Commit,
+    /// Check the `--server-seed` against its commitment, and replay the log
+    /// of the fair rolls of a session. The rolls must all have the same
+    /// client seed, the `--client-seed` when it's given, and follow each
+    /// other without any missing.
+    Verify { commitment: String, log: PathBuf },
+    /// Start the interactive prompt, even when stdin isn't a terminal.
+    Repl,
+}
+
+impl Cli {
+    fn color(&self) -> bool {
+        match self.color {
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+            ColorChoice::Auto => {
+                atty::is(atty::Stream::Stdout)
+                    && std::env::var_os("NO_COLOR").is_none_or(|v| v.is_empty())
+            }
+        }
     }
 
-    if let Some(filename) = args.get(1) {
-        run_file(filename)
-    } else if atty::is(atty::Stream::Stdin) {
-        run_prompt()
+    fn result_format(&self) -> ResultFormat {
+        match self.format {
+            _ if self.json => ResultFormat::Json,
+            Some(format) => format,
+            None if self.color() => ResultFormat::Ansi,
+            None => ResultFormat::Plain,
+        }
+    }
+}
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug)]
+enum ColorChoice {
+    Auto,
+    Always,
+    Never,
+}
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug)]
+enum ResultFormat {
+    Plain,
+    /// Colored with ANSI escape codes
+    Ansi,
+    Markdown,
+    Html,
+    Json,
+}
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug)]
+enum TreeFormat {
+    /// An indented tree
+    Ascii,
+    /// A mermaid flowchart
+    Mermaid,
+    /// A graphviz graph
+    Dot,
+}
+
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+
+    let seed = cli.seed.unwrap_or_else(rand::random);
+    let rng = match cli.server_seed {
+        Some(server_seed) => {
+            let client_seed = match (&cli.client_seed, &cli.command) {
+                (Some(client_seed), _) => client_seed.as_str(),
+                // the client seeds of the rolls to replay are in their log
+                (None, Some(Command::Replay { .. } | Command::Verify { .. })) => "",
+                (None, _) => Cli::command()
+                    .error(
+                        clap::ErrorKind::MissingRequiredArgument,
+                        "the fair rolls need the --client-seed of the players",
+                    )
+                    .exit(),
+            };
+            if cli.verbose {
+                eprintln!("commitment: {}", server_seed.commitment());
+            }
+            SessionRng::Fair(FairRng::new(server_seed, client_seed))
+        }
+        None if cli.os_random => SessionRng::Os(OsRng),
+        None => {
+            if cli.verbose {
+                eprintln!("seed: {seed}");
+            }
+            SessionRng::seeded(seed)
+        }
+    };
+    let verbosity = if cli.quiet {
+        Verbosity::Total
     } else {
-        run_file("/dev/stdin")
+        Verbosity::Breakdown
+    };
+    let config = Config {
+        verbosity,
+        ..Config::default()
+    };
+    let log = match &cli.log {
+        Some(path) => match OpenOptions::new().append(true).create(true).open(path) {
+            Ok(log) => Some(log),
+            Err(error) => return fail(SetupError::file(path, error), ""),
+        },
+        None => None,
+    };
+    let mut session = Session::new(rng, &cli.force, config, log);
+
+    let result = match &cli.command {
+        Some(Command::Roll { expressions }) => run_all(&cli, &mut session, expressions),
+        Some(Command::Stats {
+            expression,
+            samples,
+        }) => stats(seed, expression, *samples),
+        Some(Command::Check { scripts }) => check(scripts),
+        Some(Command::Fmt { script, write }) => fmt(script, *write),
+        Some(Command::Replay { log }) => replay(&cli, log, false),
+        Some(Command::Commit) => {
+            let server_seed = ServerSeed::random();
+            println!("server seed: {server_seed}");
+            println!("commitment:  {}", server_seed.commitment());
+            Ok(())
+        }
+        Some(Command::Verify { commitment, log }) => verify(&cli, commitment, log),
+        Some(Command::Repl) => repl::run_prompt(&cli, session).map_err(|error| fail(error, "")),
+        None if !cli.expressions.is_empty() => run_all(&cli, &mut session, &cli.expressions),
+        None => {
+            if let Some(filename) = &cli.script {
+                run_file(&cli, &mut session, filename)
+            } else if atty::is(atty::Stream::Stdin) {
+                repl::run_prompt(&cli, session).map_err(|error| fail(error, ""))
+            } else {
+                run_lines(&cli, &mut session, std::io::stdin().lock())
+            }
+        }
+    };
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(code) => code,
+    }
+}
+
+/// The exit status of the process after an error, see the help.
+fn exit_code(error: &Error) -> ExitCode {
+    ExitCode::from(match error {
+        Error::Unexpected(_) => 1,
+        Error::Parser(_) => 3,
+        Error::Interpreter(_) => 4,
+        Error::Setup(_) => 5,
+    })
+}
+
+/// Print the report of an error that happened while running `source`, and
+/// return the exit status of the process.
+fn fail(error: impl Into<Error>, source: &str) -> ExitCode {
+    let error = error.into();
+    let code = exit_code(&error);
+    eprintln!("{}", report(error, source));
+    code
+}
+
+/// A human readable report of an error that happened while running
+/// `source`.
+fn report(error: Error, source: &str) -> String {
+    match error {
+        Error::Parser(error) => error.to_report(),
+        Error::Interpreter(error) => error.to_report(source),
+        error => format!("{:?}", miette::Report::from(error)),
+    }
+}
+
+/// A line of the input, read on its own, whose reports show its number in
+/// the whole input.
+struct Line {
+    /// Counted from zero, like the lines of a [`miette::SpanContents`].
+    number: usize,
+    text: String,
+}
+
+impl miette::SourceCode for Line {
+    fn read_span<'a>(
+        &'a self,
+        span: &miette::SourceSpan,
+        context_lines_before: usize,
+        context_lines_after: usize,
+    ) -> std::result::Result<Box<dyn miette::SpanContents<'a> + 'a>, miette::MietteError> {
+        let contents = self
+            .text
+            .read_span(span, context_lines_before, context_lines_after)?;
+        Ok(Box::new(miette::MietteSpanContents::new(
+            contents.data(),
+            *contents.span(),
+            self.number + contents.line(),
+            contents.column(),
+            contents.line_count(),
+        )))
     }
 }
 
-fn run_file(filename: impl AsRef<Path>) -> Result<()> {
-    let source = std::fs::read_to_string(filename).map_err(SetupError::from)?;
-    run(&source)
+/// Like [`report`], for an error that happened while running a [`Line`].
+fn report_line(error: Error, line: Line) -> String {
+    match error {
+        Error::Parser(error) => format!("{:?}", miette::Report::from(error).with_source_code(line)),
+        Error::Interpreter(error) => {
+            format!("{:?}", miette::Report::from(error).with_source_code(line))
+        }
+        error => report(error, ""),
+    }
+}
+
+/// Run every expression in order, stopping at the first error unless
+/// `--continue-on-error` is set.
+fn run_all(
+    cli: &Cli,
+    session: &mut Session,
+    expressions: &[String],
+) -> std::result::Result<(), ExitCode> {
+    let mut result = Ok(());
+    for expression in expressions {
+        if let Err(error) = run(cli, session, expression) {
+            result = Err(fail(error, expression));
+            if !cli.continue_on_error {
+                break;
+            }
+        }
+    }
+    result
 }
 
-fn run_prompt() -> Result<()> {
-    let stdin = std::io::stdin();
-    let stdin = stdin.lock();
-    let mut stdout = std::io::stdout();
+/// Run every non-blank line as an expression, like [`run_all`]. The errors
+/// are reported with the number of their line.
+fn run_lines(
+    cli: &Cli,
+    session: &mut Session,
+    reader: impl BufRead,
+) -> std::result::Result<(), ExitCode> {
+    let mut result = Ok(());
+    for (number, line) in reader.lines().enumerate() {
+        let line = line.map_err(|error| fail(SetupError::from(error), ""))?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        if let Err(error) = run(cli, session, &line) {
+            let code = exit_code(&error);
+            eprintln!("{}", report_line(error, Line { number, text: line }));
+            result = Err(code);
+            if !cli.continue_on_error {
+                break;
+            }
+        }
+    }
+    result
+}
 
-    print!("> ");
-    stdout.flush().map_err(SetupError::from)?;
+fn stats(seed: u64, expression: &str, samples: usize) -> std::result::Result<(), ExitCode> {
+    let mut interpreter = Interpreter::builder()
+        .seed(seed)
+        .verbosity(Verbosity::Total)
+        .build();
+    let expr = Parser::new(expression)
+        .parse()
+        .map_err(|error| fail(error, expression))?;
+    let distribution = interpreter
+        .distribution(&expr, samples)
+        .map_err(|error| fail(error, expression))?;
+    print!("{distribution}");
+    Ok(())
+}
 
-    for line in stdin.lines() {
-        let line = line.map_err(SetupError::from)?;
-        match run(&line) {
-            Ok(_) => (),
-            Err(Error::Parser(error)) => println!("{}", error.to_report()),
-            Err(error) => println!("{:?}", error),
+fn check(scripts: &[PathBuf]) -> std::result::Result<(), ExitCode> {
+    let mut result = Ok(());
+    for script in scripts {
+        let source = read(script)?;
+        if let Err(error) = Parser::new(&source).parse() {
+            eprint!("{}: ", script.display());
+            result = Err(fail(error, &source));
         }
-        print!("> ");
-        stdout.flush().map_err(SetupError::from)?;
     }
+    result
+}
 
+fn fmt(script: &Path, write: bool) -> std::result::Result<(), ExitCode> {
+    let source = read(script)?;
+    let expr = Parser::new(&source)
+        .parse()
+        .map_err(|error| fail(error, &source))?;
+    let formatted = format!("{expr}\n");
+    if write {
+        std::fs::write(script, formatted)
+            .map_err(|error| fail(SetupError::file(script, error), ""))?;
+    } else {
+        print!("{formatted}");
+    }
     Ok(())
 }
 
-fn run(source: &str) -> Result<()> {
-    let result = Interpreter::run(source)?;
-    println!("{}", result);
+/// Roll again every entry of the log and print whether the same dice came
+/// up. The fair rolls are rolled with the `--server-seed`, and must have been
+/// rolled with the `--client-seed` when it's given.
+///
+/// When verifying a `fair` session, every entry must be a fair roll with the
+/// same client seed, starting at the counter 0 and at the counter where the
+/// previous roll stopped, so no roll was dropped or moved.
+fn replay(cli: &Cli, log: &Path, fair: bool) -> std::result::Result<(), ExitCode> {
+    let source = read(log)?;
+    let mut result = Ok(());
+    let mut session_seed = cli.client_seed.clone();
+    // where the next roll of the session starts
+    let mut next_counter = 0;
+    for (number, line) in source.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let entry: LogEntry = match serde_json::from_str(line) {
+            Ok(entry) => entry,
+            Err(error) => {
+                println!("INVALID {}: {error}", number + 1);
+                result = Err(ExitCode::from(6));
+                continue;
+            }
+        };
+        // the counter of a roll that doesn't follow the previous one
+        let mut out_of_order = None;
+        match &entry.position {
+            Position::Seeded { .. } if fair => {
+                println!(
+                    "INVALID {}: {} wasn't rolled with fair dice",
+                    number + 1,
+                    entry.source,
+                );
+                result = Err(ExitCode::from(6));
+                continue;
+            }
+            Position::Seeded { .. } => {}
+            Position::Fair {
+                client_seed,
+                counter,
+            } => {
+                if cli.server_seed.is_none() {
+                    Cli::command()
+                        .error(
+                            clap::ErrorKind::MissingRequiredArgument,
+                            "the fair rolls of the log need the --server-seed to be replayed",
+                        )
+                        .exit()
+                }
+                if fair {
+                    session_seed.get_or_insert_with(|| client_seed.clone());
+                }
+                if session_seed.as_ref().is_some_and(|c| c != client_seed) {
+                    println!(
+                        "MISMATCH {}: {} was rolled with the client seed {client_seed:?}",
+                        number + 1,
+                        entry.source,
+                    );
+                    result = Err(ExitCode::from(6));
+                    continue;
+                }
+                if fair && *counter != next_counter {
+                    out_of_order = Some(*counter);
+                }
+            }
+        }
+        let (value, next) = match entry.replay_next(cli.server_seed) {
+            Ok(replayed) => replayed,
+            Err(error) => {
+                result = Err(fail(error, &entry.source));
+                continue;
+            }
+        };
+        if let Some(counter) = out_of_order {
+            println!(
+                "MISMATCH {}: {} was rolled at the counter {counter} instead of {next_counter}, \
+                 a roll is missing or out of order",
+                number + 1,
+                entry.source,
+            );
+            result = Err(ExitCode::from(6));
+        } else if entry.matches(&value) {
+            println!("ok {}: {} = {}", number + 1, entry.source, entry.result)
+        } else {
+            println!(
+                "MISMATCH {}: {} = {}, replayed {value}",
+                number + 1,
+                entry.source,
+                entry.result,
+            );
+            result = Err(ExitCode::from(6));
+        }
+        if let Position::Fair { counter, .. } = next {
+            next_counter = counter;
+        }
+    }
+    result
+}
+
+fn verify(cli: &Cli, commitment: &str, log: &Path) -> std::result::Result<(), ExitCode> {
+    let server_seed = match cli.server_seed {
+        Some(server_seed) => server_seed,
+        None => Cli::command()
+            .error(
+                clap::ErrorKind::MissingRequiredArgument,
+                "verify needs the --server-seed of the session",
+            )
+            .exit(),
+    };
+    if !server_seed
+        .commitment()
+        .eq_ignore_ascii_case(commitment.trim())
+    {
+        eprintln!("The server seed doesn't match the commitment.");
+        return Err(ExitCode::from(6));
+    }
+    eprintln!("The server seed matches the commitment.");
+    replay(cli, log, true)
+}
+
+fn read(path: impl AsRef<Path>) -> std::result::Result<String, ExitCode> {
+    let path = path.as_ref();
+    std::fs::read_to_string(path).map_err(|error| fail(SetupError::file(path, error), ""))
+}
+
+fn run_file(
+    cli: &Cli,
+    session: &mut Session,
+    filename: impl AsRef<Path>,
+) -> std::result::Result<(), ExitCode> {
+    let source = read(filename)?;
+    run(cli, session, &source).map_err(|error| fail(error, &source))
+}
+
+fn run(cli: &Cli, session: &mut Session, source: &str) -> Result<()> {
+    let expr = Parser::new(source).parse()?;
+    if let Some(format) = cli.tree {
+        let graph = Graph::from(&expr);
+        match format {
+            TreeFormat::Ascii => print!("{}", graph.to_ascii_tree()),
+            TreeFormat::Mermaid => print!("{}", graph.to_mermaid()),
+            TreeFormat::Dot => println!("{}", graph.to_dot()),
+        }
+        return Ok(());
+    }
+    let evaluation = session.evaluate(source, &expr)?;
+    if cli.graph {
+        println!("{}", evaluation.graph());
+        return Ok(());
+    }
 
+    let verbosity = session.interpreter.config().verbosity;
+    match cli.result_format() {
+        ResultFormat::Plain => print_result(cli, &evaluation, verbosity, &Plain),
+        ResultFormat::Ansi => print_result(cli, &evaluation, verbosity, &Ansi),
+        ResultFormat::Markdown => print_result(cli, &evaluation, verbosity, &Markdown),
+        ResultFormat::Html => print_result(cli, &evaluation, verbosity, &Html),
+        ResultFormat::Json => {
+            let result = verbosity.apply(evaluation.value);
+            let json = serde_json::to_string(&result).map_err(anyhow::Error::from)?;
+            println!("{json}");
+        }
+    }
     Ok(())
 }
+
+fn print_result(
+    cli: &Cli,
+    evaluation: &Evaluation<'_, '_>,
+    verbosity: Verbosity,
+    format: &impl Format,
+) {
+    if cli.verbose {
+        print!("{}", evaluation.trace(format));
+        println!("{}", format.format(&evaluation.value));
+    } else if cli.quiet {
+        println!("{}", evaluation.value.current);
+    } else {
+        println!(
+            "{}",
+            format.format(&verbosity.apply(evaluation.value.clone()))
+        );
+    }
+}
@@ -1,6 +1,6 @@
 use logos::{Lexer, Logos};
 
-use crate::{Expr, ParserError, Token, TokenType};
+use crate::{Compare, Condition, Expr, Modifier, OwnedExpr, ParserError, Token, TokenType};
 
 type Result<T> = std::result::Result<T, ParserError>;
 
@@ -35,12 +35,17 @@ impl<'a> Parser<'a> {
                     "Unexpected characters `{:.10}` at the end of file.",
                     self.current.lexeme().to_string() + self.lexer.remainder(),
                 ),
-                label: format!("Here"),
+                label: "Here".to_string(),
                 span: self.current.span.into(),
             })
         }
     }
 
+    /// Parse the source into an expression that doesn't borrow it.
+    pub fn parse_owned(self) -> Result<OwnedExpr> {
+        self.parse().map(Expr::into_owned)
+    }
+
     fn expression(&mut self) -> Result<Expr<'a>> {
         self.term()
     }
@@ -48,7 +53,6 @@ impl<'a> Parser<'a> {
     fn term(&mut self) -> Result<Expr<'a>> {
         let mut expr = self.factor()?;
 
-        println!("looking for minus, but next token is {:?}", self.current.ty);
         while self.is_followed_by([TokenType::Minus, TokenType::Plus])? {
             let operator = self.previous.clone();
             let right = Box::new(self.factor()?);
@@ -85,26 +89,161 @@ impl<'a> Parser<'a> {
     }
 
     fn roll(&mut self) -> Result<Expr<'a>> {
-        let mut expr = self.unary()?;
+        // `d20` is a shorthand for `1d20`
+        let mut expr = if self.check(TokenType::Dice) {
+            Expr::Literal {
+                value: crate::Value::direct(1),
+            }
+        } else {
+            self.unary()?
+        };
 
         while self.is_followed_by([TokenType::Dice])? {
             let dice = self.previous.clone();
             let faces = Box::new(self.unary()?);
+            let modifiers = self.modifiers()?;
 
             expr = Expr::Roll {
                 quantity: Box::new(expr),
                 dice,
                 faces,
+                modifiers,
             };
         }
 
         Ok(expr)
     }
 
+    /// `adv` and `dis` are desugared into `2d20kh1` and `2d20kl1`.
+    fn advantage(&mut self) -> Result<Expr<'a>> {
+        let token = self.previous.clone();
+        let keep = match token.ty {
+            TokenType::Advantage => Modifier::KeepHighest {
+                token: token.clone(),
+                count: 1,
+            },
+            TokenType::Disadvantage => Modifier::KeepLowest {
+                token: token.clone(),
+                count: 1,
+            },
+            _ => unreachable!(),
+        };
+        let mut modifiers = vec![keep];
+        modifiers.extend(self.modifiers()?);
+
+        Ok(Expr::Roll {
+            quantity: Box::new(Expr::Literal {
+                value: crate::Value::direct(2),
+            }),
+            dice: token,
+            faces: Box::new(Expr::Literal {
+                value: crate::Value::direct(20),
+            }),
+            modifiers,
+        })
+    }
+
+    fn modifiers(&mut self) -> Result<Vec<Modifier<'a>>> {
+        let mut modifiers = Vec::new();
+
+        while self.is_followed_by([
+            TokenType::CriticalSuccess,
+            TokenType::CriticalFailure,
+            TokenType::KeepHighest,
+            TokenType::KeepLowest,
+            TokenType::Explode,
+        ])? {
+            let token = self.previous.clone();
+
+            modifiers.push(match token.ty {
+                TokenType::CriticalSuccess => Modifier::CriticalSuccess {
+                    token,
+                    condition: self.condition()?,
+                },
+                TokenType::CriticalFailure => Modifier::CriticalFailure {
+                    token,
+                    condition: self.condition()?,
+                },
+                TokenType::KeepHighest => Modifier::KeepHighest {
+                    token,
+                    count: self.count()?,
+                },
+                TokenType::KeepLowest => Modifier::KeepLowest {
+                    token,
+                    count: self.count()?,
+                },
+                TokenType::Explode => Modifier::Explode {
+                    token,
+                    condition: self.optional_condition()?,
+                },
+                _ => unreachable!(),
+            });
+        }
+
+        Ok(modifiers)
+    }
+
+    /// An optional number following a modifier, defaults to one.
+    fn count(&mut self) -> Result<isize> {
+        if self.is_followed_by([TokenType::Number])? {
+            match self.value()? {
+                Expr::Literal { value } => Ok(*value),
+                _ => unreachable!(),
+            }
+        } else {
+            Ok(1)
+        }
+    }
+
+    fn optional_condition(&mut self) -> Result<Option<Condition>> {
+        if [
+            TokenType::Equal,
+            TokenType::Greater,
+            TokenType::GreaterEqual,
+            TokenType::Less,
+            TokenType::LessEqual,
+            TokenType::Number,
+        ]
+        .contains(&self.current.ty)
+        {
+            Ok(Some(self.condition()?))
+        } else {
+            Ok(None)
+        }
+    }
+
+    fn condition(&mut self) -> Result<Condition> {
+        let compare = if self.is_followed_by([
+            TokenType::Equal,
+            TokenType::Greater,
+            TokenType::GreaterEqual,
+            TokenType::Less,
+            TokenType::LessEqual,
+        ])? {
+            match self.previous.ty {
+                TokenType::Equal => Compare::Equal,
+                TokenType::Greater => Compare::Greater,
+                TokenType::GreaterEqual => Compare::GreaterEqual,
+                TokenType::Less => Compare::Less,
+                TokenType::LessEqual => Compare::LessEqual,
+                _ => unreachable!(),
+            }
+        } else {
+            Compare::Equal
+        };
+
+        self.consume(TokenType::Number)?;
+        match self.value()? {
+            Expr::Literal { value } => Ok(Condition::new(compare, *value)),
+            _ => unreachable!(),
+        }
+    }
+
     fn unary(&mut self) -> Result<Expr<'a>> {
         if self.is_followed_by([TokenType::Minus])? {
             let operator = self.previous.clone();
-            let right = Box::new(self.unary()?);
+            // `-2d6` is the opposite of a whole `2d6`, and `-d6` of a `1d6`
+            let right = Box::new(self.roll()?);
 
             Ok(Expr::Unary { operator, right })
         } else {
@@ -116,6 +255,7 @@ impl<'a> Parser<'a> {
         let token = self.advance()?;
         match token.ty {
             TokenType::Number => self.value(),
+            TokenType::Advantage | TokenType::Disadvantage => self.advantage(),
             TokenType::LeftParen => {
                 let expr = self.expression()?;
                 self.consume(TokenType::RightParen)?;
@@ -197,6 +337,7 @@ impl<'a> Parser<'a> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::Value;
 
     #[test]
     fn test_value() -> Result<()> {
@@ -216,4 +357,208 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn test_critical_modifiers() -> Result<()> {
+        let expr = Parser::new("1d20cs>=19cf<=2").parse()?;
+        let modifiers = match expr {
+            Expr::Roll { modifiers, .. } => modifiers,
+            _ => panic!("expected a roll"),
+        };
+        assert!(matches!(
+            modifiers[..],
+            [
+                Modifier::CriticalSuccess {
+                    condition: Condition {
+                        compare: Compare::GreaterEqual,
+                        value: 19
+                    },
+                    ..
+                },
+                Modifier::CriticalFailure {
+                    condition: Condition {
+                        compare: Compare::LessEqual,
+                        value: 2
+                    },
+                    ..
+                },
+            ]
+        ));
+
+        let expr = Parser::new("1d20cs20").parse()?;
+        assert!(matches!(
+            expr,
+            Expr::Roll { modifiers, .. } if matches!(
+                modifiers[..],
+                [Modifier::CriticalSuccess { condition: Condition { compare: Compare::Equal, value: 20 }, .. }]
+            )
+        ));
+
+        assert!(Parser::new("1d20cs").parse().is_err());
+        assert!(Parser::new("1d20cs>=").parse().is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_advantage() -> Result<()> {
+        let expr = Parser::new("adv").parse()?;
+        assert!(matches!(
+            expr,
+            Expr::Roll { quantity, faces, modifiers, .. }
+                if *quantity == Expr::Literal { value: Value::direct(2) }
+                && *faces == Expr::Literal { value: Value::direct(20) }
+                && matches!(modifiers[..], [Modifier::KeepHighest { count: 1, .. }])
+        ));
+
+        let expr = Parser::new("dis cs>=19").parse()?;
+        assert!(matches!(
+            expr,
+            Expr::Roll { modifiers, .. } if matches!(
+                modifiers[..],
+                [Modifier::KeepLowest { count: 1, .. }, Modifier::CriticalSuccess { .. }]
+            )
+        ));
+
+        let expr = Parser::new("4d6kh3").parse()?;
+        assert!(matches!(
+            expr,
+            Expr::Roll { modifiers, .. } if matches!(modifiers[..], [Modifier::KeepHighest { count: 3, .. }])
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_explode() -> Result<()> {
+        let expr = Parser::new("3d6!").parse()?;
+        assert!(matches!(
+            expr,
+            Expr::Roll { modifiers, .. } if matches!(modifiers[..], [Modifier::Explode { condition: None, .. }])
+        ));
+
+        let expr = Parser::new("3d6!>=5kh2").parse()?;
+        assert!(matches!(
+            expr,
+            Expr::Roll { modifiers, .. } if matches!(
+                modifiers[..],
+                [
+                    Modifier::Explode {
+                        condition: Some(Condition { compare: Compare::GreaterEqual, value: 5 }),
+                        ..
+                    },
+                    Modifier::KeepHighest { count: 2, .. },
+                ]
+            )
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_serde_round_trip() -> Result<()> {
+        let sources = [
+            "1",
+            "-(2 + 3) × 4 ÷ 2",
+            "4d6kh3 + 2d8!>=7 - 1d20cs>=19cf<=2",
+            "adv + dis cs19",
+        ];
+
+        for source in sources {
+            let expr = Parser::new(source).parse()?;
+            let json = serde_json::to_string(&expr).unwrap();
+            let deserialized: Expr<'static> = serde_json::from_str(&json).unwrap();
+            assert_eq!(deserialized, expr, "{source}");
+            assert_eq!(expr.clone().into_owned(), expr, "{source}");
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_unicode_end_of_file() {
+        let result = Parser::new("1 −").parse();
+        assert!(matches!(result, Err(ParserError { span, .. }) if span == (2..5).into()));
+    }
+
+    #[test]
+    fn test_owned() -> Result<()> {
+        fn assert_send_sync<T: Send + Sync + 'static>(_: &T) {}
+
+        let mut cache = std::collections::HashMap::new();
+        for source in ["11d6", "1 + 2"] {
+            let source = source.to_string();
+            let expr = Parser::new(&source).parse_owned()?;
+            assert_send_sync(&expr);
+            cache.insert(source.clone(), expr);
+        }
+
+        let expr = cache.remove("11d6").unwrap();
+        let result = std::thread::spawn(move || {
+            let mut interpreter = crate::Interpreter::builder().max_dice(10).build();
+            interpreter.interpret(&expr)
+        })
+        .join()
+        .unwrap();
+        assert!(matches!(
+            result,
+            Err(crate::InterpreterError::TooManyDice { span, .. }) if span == (2..3).into()
+        ));
+
+        let mut interpreter = crate::Interpreter::default();
+        assert_eq!(interpreter.interpret(&cache["1 + 2"]).unwrap(), 3);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_implicit_quantity() -> Result<()> {
+        let expr = Parser::new("d20 + 2").parse()?;
+        assert!(matches!(
+            expr,
+            Expr::Binary { left, .. } if matches!(
+                &*left,
+                Expr::Roll { quantity, .. } if **quantity == Expr::Literal { value: Value::direct(1) }
+            )
+        ));
+
+        let expr = Parser::new("2 * -d6").parse()?;
+        assert!(matches!(
+            expr,
+            Expr::Binary { right, .. } if matches!(
+                &*right,
+                Expr::Unary { right, .. } if matches!(
+                    &**right,
+                    Expr::Roll { quantity, .. } if **quantity == Expr::Literal { value: Value::direct(1) }
+                )
+            )
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_negative_roll() -> Result<()> {
+        let expr = Parser::new("-2d6").parse()?;
+        assert!(matches!(
+            expr,
+            Expr::Unary { right, .. } if matches!(
+                &*right,
+                Expr::Roll { quantity, .. } if **quantity == Expr::Literal { value: Value::direct(2) }
+            )
+        ));
+
+        // the quantity can still be negative between parentheses
+        let expr = Parser::new("(-2)d6").parse()?;
+        assert!(matches!(
+            expr,
+            Expr::Roll { quantity, .. } if matches!(&*quantity, Expr::Grouping { .. })
+        ));
+
+        let expr = Parser::new("-2 * 3").parse()?;
+        assert!(matches!(
+            expr,
+            Expr::Binary { left, .. } if matches!(&*left, Expr::Unary { .. })
+        ));
+        Ok(())
+    }
 }
@@ -0,0 +1,259 @@
+use crate::{Critical, Die, Kind, Value};
+
+/// A way to render a [`Value`] and its breakdown for a specific output.
+///
+/// Only [`Format::die`] has to be implemented, the other methods have sensible
+/// defaults. The layout is the same as the one of the `Display` of [`Value`],
+/// `7 <= (7 (3 + 4))`.
+pub trait Format {
+    /// The total of the value, at the start of the output.
+    fn total(&self, total: isize) -> String {
+        total.to_string()
+    }
+
+    /// A number of the breakdown that didn't come from a die.
+    fn number(&self, number: isize) -> String {
+        number.to_string()
+    }
+
+    fn die(&self, die: &Die) -> String;
+
+    /// Operators, spaces and parentheses.
+    fn token(&self, token: &str) -> String {
+        token.to_string()
+    }
+
+    fn format(&self, value: &Value) -> String {
+        let mut res = self.total(value.current);
+        res.push_str(&self.token(" <= ("));
+        self.format_kinds(&value.all, &mut res);
+        res.push_str(&self.token(")"));
+        res
+    }
+
+    fn format_kinds(&self, kinds: &[Kind], res: &mut String) {
+        for (i, kind) in kinds.iter().enumerate() {
+            if i != 0 {
+                res.push_str(&self.token(" "));
+            }
+            match kind {
+                Kind::Direct(number) => res.push_str(&self.number(*number)),
+                Kind::Die(die) => res.push_str(&self.die(die)),
+                Kind::Roll(roll) => {
+                    res.push_str(&self.token("("));
+                    self.format_kinds(roll, res);
+                    res.push_str(&self.token(")"));
+                }
+                Kind::Token(token) => res.push_str(&self.token(token)),
+            }
+        }
+    }
+}
+
+/// The same output as the `Display` of [`Value`].
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Plain;
+
+impl Format for Plain {
+    fn die(&self, die: &Die) -> String {
+        die.to_string()
+    }
+}
+
+/// Colors for a terminal, with ANSI escape codes. The total is highlighted,
+/// the max faces are green, the min faces red, and they're bold if they're
+/// also critical. The operators and the dropped dice are dimmed.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Ansi;
+
+impl Format for Ansi {
+    fn total(&self, total: isize) -> String {
+        format!("\x1b[1;4m{total}\x1b[0m")
+    }
+
+    fn die(&self, die: &Die) -> String {
+        let bang = if die.exploded { "!" } else { "" };
+        let value = die.value;
+        let color = match die.critical {
+            _ if die.dropped => "2;9",
+            Some(Critical::Success) if value == die.faces => "1;32",
+            Some(Critical::Failure) if value == 1 => "1;31",
+            Some(Critical::Success) => "1",
+            Some(Critical::Failure) => "1",
+            None if value == die.faces => "32",
+            None if value == 1 => "31",
+            None => return format!("{value}{bang}"),
+        };
+        format!("\x1b[{color}m{value}\x1b[0m{bang}")
+    }
+
+    fn token(&self, token: &str) -> String {
+        if token.trim().is_empty() {
+            token.to_string()
+        } else {
+            format!("\x1b[2m{token}\x1b[0m")
+        }
+    }
+}
+
+/// Markdown, as understood by most chat applications. The total is bold,
+/// the critical successes are bold, the critical failures are italic and
+/// the dropped dice are struck through.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Markdown;
+
+impl Format for Markdown {
+    fn total(&self, total: isize) -> String {
+        format!("**{total}**")
+    }
+
+    fn die(&self, die: &Die) -> String {
+        let bang = if die.exploded { "!" } else { "" };
+        let value = die.value;
+        match die.critical {
+            _ if die.dropped => format!("~~{value}~~{bang}"),
+            Some(Critical::Success) => format!("**{value}**{bang}"),
+            Some(Critical::Failure) => format!("*{value}*{bang}"),
+            None => format!("{value}{bang}"),
+        }
+    }
+
+    fn token(&self, token: &str) -> String {
+        let mut res = String::with_capacity(token.len());
+        for c in token.chars() {
+            if "\\`*_~|".contains(c) {
+                res.push('\\');
+            }
+            res.push(c);
+        }
+        res
+    }
+}
+
+/// An HTML fragment. The critical dice are colored with inline styles, since
+/// the chat applications usually drop the stylesheets, and have a class.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Html;
+
+impl Format for Html {
+    fn total(&self, total: isize) -> String {
+        format!("<strong>{total}</strong>")
+    }
+
+    fn die(&self, die: &Die) -> String {
+        let bang = if die.exploded { "!" } else { "" };
+        let value = die.value;
+        match die.critical {
+            _ if die.dropped => format!("<del>{value}</del>{bang}"),
+            Some(Critical::Success) => format!(
+                r#"<span class="critical-success" style="color: green">{value}</span>{bang}"#
+            ),
+            Some(Critical::Failure) => {
+                format!(r#"<span class="critical-failure" style="color: red">{value}</span>{bang}"#)
+            }
+            None => format!("{value}{bang}"),
+        }
+    }
+
+    fn token(&self, token: &str) -> String {
+        let mut res = String::with_capacity(token.len());
+        for c in token.chars() {
+            match c {
+                '<' => res.push_str("&lt;"),
+                '>' => res.push_str("&gt;"),
+                '&' => res.push_str("&amp;"),
+                '"' => res.push_str("&quot;"),
+                '\'' => res.push_str("&#39;"),
+                c => res.push(c),
+            }
+        }
+        res
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn value() -> Value {
+        let die = |value, critical, dropped, exploded| {
+            Kind::Die(Die {
+                value,
+                faces: 20,
+                critical,
+                dropped,
+                exploded,
+            })
+        };
+        let roll = Kind::Roll(vec![
+            die(20, Some(Critical::Success), false, true),
+            Kind::Token("+".to_string()),
+            die(1, Some(Critical::Failure), false, false),
+            Kind::Token("+".to_string()),
+            die(7, None, true, false),
+            Kind::Token("+".to_string()),
+            die(4, None, false, false),
+        ]);
+        Value::new(25, vec![Kind::Direct(25), roll]) - Value::direct(2)
+    }
+
+    #[test]
+    fn test_plain() {
+        let value = value();
+        assert_eq!(Plain.format(&value), value.to_string());
+        assert_eq!(
+            Plain.format(&value),
+            "23 <= (25 (*20*! + _1_ + ~7~ + 4) - 2)"
+        );
+    }
+
+    #[test]
+    fn test_ansi() {
+        let dim = |token| format!("\x1b[2m{token}\x1b[0m");
+        assert_eq!(
+            Ansi.format(&value()),
+            format!(
+                "\x1b[1;4m23\x1b[0m{}25 {}\x1b[1;32m20\x1b[0m! {} \x1b[1;31m1\x1b[0m {} \x1b[2;9m7\x1b[0m {} 4{} {} 2{}",
+                dim(" <= ("),
+                dim("("),
+                dim("+"),
+                dim("+"),
+                dim("+"),
+                dim(")"),
+                dim("-"),
+                dim(")"),
+            )
+        );
+
+        let die = |value, critical| Die {
+            value,
+            faces: 6,
+            critical,
+            dropped: false,
+            exploded: false,
+        };
+        assert_eq!(Ansi.die(&die(6, None)), "\x1b[32m6\x1b[0m");
+        assert_eq!(Ansi.die(&die(1, None)), "\x1b[31m1\x1b[0m");
+        assert_eq!(
+            Ansi.die(&die(5, Some(Critical::Success))),
+            "\x1b[1m5\x1b[0m"
+        );
+        assert_eq!(Ansi.die(&die(3, None)), "3");
+    }
+
+    #[test]
+    fn test_markdown() {
+        assert_eq!(
+            Markdown.format(&value()),
+            r"**23** <= (25 (**20**! + *1* + ~~7~~ + 4) - 2)"
+        );
+    }
+
+    #[test]
+    fn test_html() {
+        assert_eq!(
+            Html.format(&value()),
+            r#"<strong>23</strong> &lt;= (25 (<span class="critical-success" style="color: green">20</span>! + <span class="critical-failure" style="color: red">1</span> + <del>7</del> + 4) - 2)"#
+        );
+    }
+}
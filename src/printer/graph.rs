@@ -1,64 +1,495 @@
-use crate::Expr;
+use std::fmt::Write;
 
-impl Expr<'_> {
-    pub fn graph(&self) -> String {
-        let mut res = String::new();
-        res.push_str("digraph G {\n\t");
-        res.push_str(&self._graph(&mut 0));
-        res.push_str("}");
+use crate::{Evaluation, Expr};
+
+/// A tree of labelled nodes, built from an [`Expr`] or an [`Evaluation`],
+/// that can be rendered in the graphviz or mermaid formats.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Graph {
+    nodes: Vec<Node>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Node {
+    /// Every line of the label, they're escaped when the graph is rendered.
+    pub label: Vec<String>,
+    pub children: Vec<usize>,
+}
+
+impl Graph {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a node and return its id.
+    pub fn add_node(&mut self, label: Vec<String>) -> usize {
+        self.nodes.push(Node {
+            label,
+            children: Vec::new(),
+        });
+        self.nodes.len() - 1
+    }
+
+    pub fn add_edge(&mut self, parent: usize, child: usize) {
+        self.nodes[parent].children.push(child);
+    }
+
+    pub fn nodes(&self) -> &[Node] {
+        &self.nodes
+    }
+
+    pub fn to_dot(&self) -> String {
+        let mut res = String::from("digraph G {\n");
+        for (id, node) in self.nodes.iter().enumerate() {
+            let label = node
+                .label
+                .iter()
+                .map(|line| escape_dot(line))
+                .collect::<Vec<_>>()
+                .join("\\n");
+            writeln!(res, "\tn{id} [label=\"{label}\"];").unwrap();
+        }
+        for (id, node) in self.nodes.iter().enumerate() {
+            for child in &node.children {
+                writeln!(res, "\tn{id} -> n{child};").unwrap();
+            }
+        }
+        res.push('}');
+
+        res
+    }
+
+    pub fn to_mermaid(&self) -> String {
+        let mut res = String::from("flowchart TD\n");
+        for (id, node) in self.nodes.iter().enumerate() {
+            let label = node
+                .label
+                .iter()
+                .map(|line| escape_mermaid(line))
+                .collect::<Vec<_>>()
+                .join("<br>");
+            writeln!(res, "    n{id}[\"{label}\"]").unwrap();
+        }
+        for (id, node) in self.nodes.iter().enumerate() {
+            for child in &node.children {
+                writeln!(res, "    n{id} --> n{child}").unwrap();
+            }
+        }
 
         res
     }
 
-    fn _graph(&self, count: &mut usize) -> String {
+    /// An indented tree where the lines of the labels are joined by spaces.
+    pub fn to_ascii_tree(&self) -> String {
         let mut res = String::new();
+        if !self.nodes.is_empty() {
+            self.ascii_node(0, "", "", &mut res);
+        }
+        res
+    }
+
+    fn ascii_node(&self, id: usize, prefix: &str, children_prefix: &str, res: &mut String) {
+        let node = &self.nodes[id];
+        let label = node.label.iter().flat_map(|line| line.lines());
+        writeln!(res, "{prefix}{}", label.collect::<Vec<_>>().join(" ")).unwrap();
+
+        for (i, child) in node.children.iter().enumerate() {
+            let (prefix, next) = if i + 1 == node.children.len() {
+                ("└── ", "    ")
+            } else {
+                ("├── ", "│   ")
+            };
+            self.ascii_node(
+                *child,
+                &format!("{children_prefix}{prefix}"),
+                &format!("{children_prefix}{next}"),
+                res,
+            );
+        }
+    }
+}
+
+/// Escape a string to be used between double quotes in the graphviz format.
+fn escape_dot(s: &str) -> String {
+    let mut res = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '"' => res.push_str("\\\""),
+            '\\' => res.push_str("\\\\"),
+            '\n' => res.push_str("\\n"),
+            '\r' => (),
+            c => res.push(c),
+        }
+    }
+    res
+}
+
+/// Escape a string to be used between double quotes in a mermaid label,
+/// with entity codes.
+fn escape_mermaid(s: &str) -> String {
+    let mut res = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '#' => res.push_str("#35;"),
+            '"' => res.push_str("#quot;"),
+            '<' => res.push_str("#lt;"),
+            '>' => res.push_str("#gt;"),
+            '&' => res.push_str("#amp;"),
+            '\n' => res.push_str("<br>"),
+            '\r' => (),
+            c => res.push(c),
+        }
+    }
+    res
+}
+
+impl From<&Expr<'_>> for Graph {
+    fn from(expr: &Expr<'_>) -> Self {
+        let mut graph = Graph::new();
+        expr.add_to_graph(&mut graph);
+        graph
+    }
+}
+
+impl From<&Evaluation<'_, '_>> for Graph {
+    fn from(evaluation: &Evaluation<'_, '_>) -> Self {
+        let mut graph = Graph::new();
+        evaluation.add_to_graph(&mut graph);
+        graph
+    }
+}
+
+impl Expr<'_> {
+    /// The syntax tree in the graphviz format.
+    pub fn graph(&self) -> String {
+        Graph::from(self).to_dot()
+    }
 
+    /// The label of this node, without its children.
+    fn label(&self) -> Vec<String> {
         match self {
-            Self::Binary {
-                left,
-                right,
-                operator,
-            } => {
-                let id = format!("binary_{count}");
-                *count += 1;
-                res.push_str(&format!("{id}\n"));
-                res.push_str(&format!("\t{id} [label=\"{}\"]\n", operator.lexeme()));
-                res.push_str(&format!("\t{id} -> {}", left._graph(count)));
-                res.push_str(&format!("\t{id} -> {}", right._graph(count)));
-            }
-            Self::Grouping { expression } => {
-                let id = format!("group_{count}");
-                *count += 1;
-                res.push_str(&format!("{id}\n"));
-                res.push_str(&format!("\t{id} [label=\"group\"]\n"));
-                res.push_str(&format!("\t{id} -> {}", expression._graph(count)));
-            }
-            Self::Literal { value } => {
-                let id = format!("literal_{count}");
-                *count += 1;
-                res.push_str(&format!("{id}\n"));
-                res.push_str(&format!("\t{id} [label=\"{}\"]\n", value));
-            }
+            Self::Binary { operator, .. } => vec![operator.lexeme().to_string()],
+            Self::Grouping { .. } => vec!["group".to_string()],
+            Self::Literal { value } => vec![value.current.to_string()],
             Self::Roll {
-                quantity,
-                dice,
-                faces,
+                dice, modifiers, ..
             } => {
-                let id = format!("roll_{count}");
-                *count += 1;
-                res.push_str(&format!("{id}\n"));
-                res.push_str(&format!("\t{id} [label=\"{}\"]\n", dice.lexeme()));
-                res.push_str(&format!("\t{id} -> {}", quantity._graph(count)));
-                res.push_str(&format!("\t{id} -> {}", faces._graph(count)));
+                let mut label = vec![dice.lexeme().to_string()];
+                if !modifiers.is_empty() {
+                    label.push(modifiers.iter().map(|m| m.to_string()).collect());
+                }
+                label
             }
-            Self::Unary { right, operator } => {
-                let id = format!("unary_{count}");
-                *count += 1;
-                res.push_str(&format!("{id}\n"));
-                res.push_str(&format!("\t{id} [label=\"{}\"]\n", operator.lexeme()));
-                res.push_str(&format!("\t{id} -> {}", right._graph(count)));
+            Self::Unary { operator, .. } => vec![operator.lexeme().to_string()],
+        }
+    }
+
+    /// The direct sub-expressions, in the order they appear in the source.
+    fn children(&self) -> Vec<&Self> {
+        match self {
+            Self::Binary { left, right, .. } => vec![left, right],
+            Self::Grouping { expression } => vec![expression],
+            Self::Literal { .. } => vec![],
+            Self::Roll {
+                quantity, faces, ..
+            } => vec![quantity, faces],
+            Self::Unary { right, .. } => vec![right],
+        }
+    }
+
+    fn add_to_graph(&self, graph: &mut Graph) -> usize {
+        let id = graph.add_node(self.label());
+        for child in self.children() {
+            let child = child.add_to_graph(graph);
+            graph.add_edge(id, child);
+        }
+        id
+    }
+}
+
+impl Evaluation<'_, '_> {
+    /// Like [`Expr::graph`], but every node is annotated with its value and
+    /// every roll with the dice it threw.
+    pub fn graph(&self) -> String {
+        Graph::from(self).to_dot()
+    }
+
+    fn label(&self) -> Vec<String> {
+        let mut label = self.expr.label();
+        if let Expr::Roll { .. } = self.expr {
+            let dice = self
+                .value
+                .dice()
+                .iter()
+                .map(|die| die.to_string())
+                .collect::<Vec<_>>()
+                .join(", ");
+            label.push(format!("[{dice}]"));
+        }
+        label.push(format!("= {}", self.value.current));
+        label
+    }
+
+    fn add_to_graph(&self, graph: &mut Graph) -> usize {
+        let id = graph.add_node(self.label());
+        for child in &self.children {
+            let child = child.add_to_graph(graph);
+            graph.add_edge(id, child);
+        }
+        id
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Interpreter, Parser};
+
+    #[test]
+    fn test_expr_graph() {
+        let expr = Parser::new("-4d6kh3 + (2)").parse().unwrap();
+        assert_eq!(
+            expr.graph(),
+            r#"digraph G {
+	n0 [label="+"];
+	n1 [label="-"];
+	n2 [label="d\nkh3"];
+	n3 [label="4"];
+	n4 [label="6"];
+	n5 [label="group"];
+	n6 [label="2"];
+	n0 -> n1;
+	n0 -> n5;
+	n1 -> n2;
+	n2 -> n3;
+	n2 -> n4;
+	n5 -> n6;
+}"#
+        );
+    }
+
+    #[test]
+    fn test_evaluation_graph() {
+        let expr = Parser::new("2d1 * (3 - 1)").parse().unwrap();
+        let mut interpreter = Interpreter::default();
+        let evaluation = interpreter.evaluate(&expr).unwrap();
+        assert_eq!(
+            evaluation.graph(),
+            r#"digraph G {
+	n0 [label="*\n= 4"];
+	n1 [label="d\n[*1*, *1*]\n= 2"];
+	n2 [label="2\n= 2"];
+	n3 [label="1\n= 1"];
+	n4 [label="group\n= 2"];
+	n5 [label="-\n= 2"];
+	n6 [label="3\n= 3"];
+	n7 [label="1\n= 1"];
+	n0 -> n1;
+	n0 -> n4;
+	n1 -> n2;
+	n1 -> n3;
+	n4 -> n5;
+	n5 -> n6;
+	n5 -> n7;
+}"#
+        );
+    }
+
+    #[test]
+    fn test_mermaid() {
+        let expr = Parser::new("1d20cs>=19 + 1").parse().unwrap();
+        assert_eq!(
+            Graph::from(&expr).to_mermaid(),
+            r#"flowchart TD
+    n0["+"]
+    n1["d<br>cs#gt;=19"]
+    n2["1"]
+    n3["20"]
+    n4["1"]
+    n0 --> n1
+    n0 --> n4
+    n1 --> n2
+    n1 --> n3
+"#
+        );
+    }
+
+    #[test]
+    fn test_ascii_tree() {
+        let expr = Parser::new("-4d6kh3 + (2 * 3)").parse().unwrap();
+        assert_eq!(
+            Graph::from(&expr).to_ascii_tree(),
+            "\
++
+├── -
+│   └── d kh3
+│       ├── 4
+│       └── 6
+└── group
+    └── *
+        ├── 2
+        └── 3
+"
+        );
+
+        let expr = Parser::new("2d1").parse().unwrap();
+        let evaluation = Interpreter::default().evaluate(&expr).unwrap();
+        assert_eq!(
+            Graph::from(&evaluation).to_ascii_tree(),
+            "\
+d [*1*, *1*] = 2
+├── 2 = 2
+└── 1 = 1
+"
+        );
+        assert_eq!(
+            tricky_graph().to_ascii_tree().lines().nth(1),
+            Some("└── multi line")
+        );
+    }
+
+    fn tricky_graph() -> Graph {
+        let mut graph = Graph::new();
+        let root = graph.add_node(vec![
+            r#"say "hi" \ then"#.to_string(),
+            "# <b>bold</b> & co".to_string(),
+        ]);
+        let child = graph.add_node(vec!["multi\nline\r".to_string()]);
+        graph.add_edge(root, child);
+        graph
+    }
+
+    #[test]
+    fn test_dot_parses() {
+        let dot = tricky_graph().to_dot();
+        assert_eq!(
+            dot,
+            r#"digraph G {
+	n0 [label="say \"hi\" \\ then\n# <b>bold</b> & co"];
+	n1 [label="multi\nline"];
+	n0 -> n1;
+}"#
+        );
+        let mut parser = layout::gv::DotParser::new(&dot);
+        let graph = parser.process().expect("the dot output should parse");
+        let mut builder = layout::gv::GraphBuilder::new();
+        builder.visit_graph(&graph);
+        let graph = builder.get();
+        assert_eq!(graph.num_nodes(), 2);
+
+        let expr = Parser::new("-(4d6kh3 / 2) + 1d20cs>=19cf<2 - adv")
+            .parse()
+            .unwrap();
+        let dot = Interpreter::default().evaluate(&expr).unwrap().graph();
+        let mut parser = layout::gv::DotParser::new(&dot);
+        assert!(parser.process().is_ok(), "{dot}");
+    }
+
+    #[test]
+    fn test_mermaid_escaped() {
+        let mermaid = tricky_graph().to_mermaid();
+        assert_eq!(
+            mermaid,
+            r#"flowchart TD
+    n0["say #quot;hi#quot; \ then<br>#35; #lt;b#gt;bold#lt;/b#gt; #amp; co"]
+    n1["multi<br>line"]
+    n0 --> n1
+"#
+        );
+    }
+
+    /// Read a Mermaid flowchart back, panicking on anything outside of its
+    /// grammar: the `flowchart` header, the nodes with an id and a quoted
+    /// label, and the `-->` edges between declared nodes. Returns the
+    /// unescaped labels and the edges.
+    fn parse_mermaid(mermaid: &str) -> (Vec<String>, Vec<(usize, usize)>) {
+        let id = |id: &str| -> usize {
+            let id = id.strip_prefix('n').and_then(|id| id.parse().ok());
+            id.unwrap_or_else(|| panic!("invalid node id in {mermaid}"))
+        };
+        let mut lines = mermaid.lines();
+        assert_eq!(lines.next(), Some("flowchart TD"));
+        let (mut labels, mut edges) = (Vec::new(), Vec::new());
+        for line in lines {
+            let line = line
+                .strip_prefix("    ")
+                .unwrap_or_else(|| panic!("unindented line {line:?}"));
+            if let Some((parent, child)) = line.split_once(" --> ") {
+                let (parent, child) = (id(parent), id(child));
+                assert!(
+                    parent < labels.len() && child < labels.len(),
+                    "edge between undeclared nodes {line:?}"
+                );
+                edges.push((parent, child));
+            } else {
+                let (node, label) = line
+                    .split_once("[\"")
+                    .unwrap_or_else(|| panic!("invalid node {line:?}"));
+                assert_eq!(id(node), labels.len(), "{line:?}");
+                let label = label
+                    .strip_suffix("\"]")
+                    .unwrap_or_else(|| panic!("unquoted label {line:?}"));
+                labels.push(unescape_mermaid(label));
             }
         }
+        (labels, edges)
+    }
+
+    fn unescape_mermaid(label: &str) -> String {
+        let label = label.replace("<br>", "\n");
+        assert!(
+            !label.contains(['"', '<', '>']),
+            "unescaped character in {label:?}"
+        );
+        let mut res = String::new();
+        let mut rest = label.as_str();
+        while let Some(start) = rest.find('#') {
+            res.push_str(&rest[..start]);
+            let (entity, after) = rest[start + 1..]
+                .split_once(';')
+                .unwrap_or_else(|| panic!("unterminated entity in {label:?}"));
+            res.push(match entity {
+                "35" => '#',
+                "quot" => '"',
+                "lt" => '<',
+                "gt" => '>',
+                "amp" => '&',
+                _ => panic!("unknown entity #{entity}; in {label:?}"),
+            });
+            rest = after;
+        }
+        res.push_str(rest);
         res
     }
+
+    /// The Mermaid output must parse back to the same nodes and edges.
+    fn assert_mermaid_parses(graph: &Graph) {
+        let mermaid = graph.to_mermaid();
+        let (labels, edges) = parse_mermaid(&mermaid);
+        let expected: Vec<_> = graph
+            .nodes()
+            .iter()
+            .map(|node| node.label.join("\n").replace('\r', ""))
+            .collect();
+        assert_eq!(labels, expected, "{mermaid}");
+        let expected: Vec<_> = graph
+            .nodes()
+            .iter()
+            .enumerate()
+            .flat_map(|(id, node)| node.children.iter().map(move |&child| (id, child)))
+            .collect();
+        assert_eq!(edges, expected, "{mermaid}");
+    }
+
+    #[test]
+    fn test_mermaid_parses() {
+        assert_mermaid_parses(&tricky_graph());
+
+        let expr = Parser::new("-(4d6kh3 / 2) + 1d20cs>=19cf<2 - adv")
+            .parse()
+            .unwrap();
+        assert_mermaid_parses(&Graph::from(&expr));
+        let evaluation = Interpreter::default().evaluate(&expr).unwrap();
+        assert_mermaid_parses(&Graph::from(&evaluation));
+    }
 }
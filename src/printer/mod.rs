@@ -1,2 +1,9 @@
+mod format;
+pub use format::*;
+
 mod graph;
 pub use graph::*;
+
+mod source;
+
+mod trace;
@@ -0,0 +1,340 @@
+use std::fmt::{Display, Formatter, Result};
+
+use crate::{Compare, Condition, Expr, Modifier, TokenType};
+
+/// Print the expression back as a normalized source. Unnecessary
+/// parentheses are removed, the operators are written in ASCII and the
+/// implicit quantities are written, `2 × (3)` is printed as `2 * 3` and `d20`
+/// as `1d20`. The `adv` and `dis` shorthands are kept as they are, since they
+/// don't need the same extensions as the rolls they stand for, and their
+/// modifiers are spaced from them like in `dis cs>=19`.
+impl Display for Expr<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        match self {
+            Expr::Unary { operator, right } => {
+                write!(f, "{}", symbol(operator.ty))?;
+                right.fmt_operand(f, self.precedence())
+            }
+            Expr::Binary {
+                left,
+                operator,
+                right,
+            } => {
+                left.fmt_operand(f, self.precedence())?;
+                write!(f, " {} ", symbol(operator.ty))?;
+                // every operator is left associative
+                right.fmt_operand(f, self.precedence() + 1)
+            }
+            Expr::Grouping { expression } => expression.fmt(f),
+            Expr::Literal { value } => write!(f, "{}", value.current),
+            Expr::Roll {
+                dice, modifiers, ..
+            } if is_shorthand(dice.ty) => {
+                write!(f, "{}", dice.ty)?;
+                // the first modifier is the keep the shorthand stands for, the
+                // others are spaced from the keyword
+                if modifiers.len() > 1 {
+                    write!(f, " ")?;
+                }
+                modifiers[1..]
+                    .iter()
+                    .try_for_each(|modifier| modifier.fmt(f))
+            }
+            Expr::Roll {
+                quantity,
+                faces,
+                modifiers,
+                ..
+            } => {
+                // the `d` would be read as a part of a negated roll
+                if quantity.takes_dice() {
+                    write!(f, "({quantity})")?;
+                } else {
+                    quantity.fmt_operand(f, self.precedence())?;
+                }
+                write!(f, "d")?;
+                // the faces are read like the operand of a `-`, but without
+                // its rolls, and the modifiers would be read as the ones of a
+                // roll ending the faces, like a trailing `adv`
+                if faces.precedence() == self.precedence()
+                    || !modifiers.is_empty() && faces.takes_modifiers()
+                {
+                    write!(f, "({faces})")?;
+                } else {
+                    faces.fmt_operand(f, 2)?;
+                }
+                modifiers.iter().try_for_each(|modifier| modifier.fmt(f))
+            }
+        }
+    }
+}
+
+impl Expr<'_> {
+    /// How tightly the expression binds, following the rules of the parser.
+    fn precedence(&self) -> u8 {
+        match self {
+            Expr::Binary { operator, .. } => match operator.ty {
+                TokenType::Plus | TokenType::Minus => 0,
+                _ => 1,
+            },
+            // `-2d6` is the opposite of a whole `2d6`
+            Expr::Unary { .. } => 2,
+            Expr::Roll { dice, .. } if is_shorthand(dice.ty) => 4,
+            Expr::Roll { .. } => 3,
+            Expr::Grouping { expression } => expression.precedence(),
+            Expr::Literal { .. } => 4,
+        }
+    }
+
+    /// Whether a `d` printed right after the expression would be read as a
+    /// part of it, like in `1d-6`.
+    fn takes_dice(&self) -> bool {
+        match self {
+            Expr::Unary { .. } => true,
+            Expr::Roll {
+                dice,
+                faces,
+                modifiers,
+                ..
+            } => !is_shorthand(dice.ty) && modifiers.is_empty() && faces.precedence() == 2,
+            Expr::Grouping { expression } => expression.takes_dice(),
+            Expr::Binary { .. } | Expr::Literal { .. } => false,
+        }
+    }
+
+    /// Whether modifiers printed right after the expression would be read as
+    /// the ones of a roll ending it, like in `-adv`.
+    fn takes_modifiers(&self) -> bool {
+        match self {
+            Expr::Roll { .. } => true,
+            Expr::Unary { right, .. } => right.takes_modifiers(),
+            Expr::Grouping { expression } => expression.takes_modifiers(),
+            Expr::Binary { .. } | Expr::Literal { .. } => false,
+        }
+    }
+
+    /// Print the expression, between parentheses if it doesn't bind at
+    /// least as tightly as `precedence`.
+    fn fmt_operand(&self, f: &mut Formatter<'_>, precedence: u8) -> Result {
+        if self.precedence() < precedence {
+            write!(f, "({self})")
+        } else {
+            write!(f, "{self}")
+        }
+    }
+}
+
+impl Display for Modifier<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        match self {
+            Modifier::CriticalSuccess { condition, .. } => write!(f, "cs{condition}"),
+            Modifier::CriticalFailure { condition, .. } => write!(f, "cf{condition}"),
+            Modifier::KeepHighest { count, .. } => write!(f, "kh{count}"),
+            Modifier::KeepLowest { count, .. } => write!(f, "kl{count}"),
+            Modifier::Explode {
+                condition: Some(condition),
+                ..
+            } => write!(f, "!{condition}"),
+            Modifier::Explode {
+                condition: None, ..
+            } => write!(f, "!"),
+        }
+    }
+}
+
+impl Display for Condition {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        write!(f, "{}{}", self.compare, self.value)
+    }
+}
+
+impl Display for Compare {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        match self {
+            Compare::Equal => write!(f, "="),
+            Compare::Greater => write!(f, ">"),
+            Compare::GreaterEqual => write!(f, ">="),
+            Compare::Less => write!(f, "<"),
+            Compare::LessEqual => write!(f, "<="),
+        }
+    }
+}
+
+fn is_shorthand(ty: TokenType) -> bool {
+    matches!(ty, TokenType::Advantage | TokenType::Disadvantage)
+}
+
+fn symbol(ty: TokenType) -> &'static str {
+    match ty {
+        TokenType::Plus => "+",
+        TokenType::Minus => "-",
+        TokenType::Star | TokenType::Multiplication => "*",
+        TokenType::Slash | TokenType::Division => "/",
+        _ => unreachable!(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+
+    use super::symbol;
+    use crate::{Expr, Extensions, Interpreter, Parser};
+
+    #[test]
+    fn test_print() {
+        let test_values = [
+            ("1", "1"),
+            ("2 × 3", "2 * 3"),
+            ("2 x (3)", "2 * 3"),
+            ("((1 + 2))", "1 + 2"),
+            ("(1 + 2) + 3", "1 + 2 + 3"),
+            ("1 + (2 + 3)", "1 + (2 + 3)"),
+            ("1 − (2 − 3)", "1 - (2 - 3)"),
+            ("(1 + 2) * 3", "(1 + 2) * 3"),
+            ("1 + (2 * 3)", "1 + 2 * 3"),
+            ("100 ÷ (2 / 2)", "100 / (2 / 2)"),
+            ("-(1)", "-1"),
+            ("-(1 + 2)", "-(1 + 2)"),
+            ("d20", "1d20"),
+            ("(2)d(6)", "2d6"),
+            ("(1 + 1)d6", "(1 + 1)d6"),
+            ("2d6d4", "2d6d4"),
+            ("2d(6d4)", "2d(6d4)"),
+            ("-1d-6", "-1d-6"),
+            ("(-1)d6", "(-1)d6"),
+            ("(1d-6)d4", "(1d-6)d4"),
+            ("1d-(2d6)kh1", "1d(-2d6)kh1"),
+            ("-(2d6)", "-2d6"),
+            ("4d6kh3 + 2", "4d6kh3 + 2"),
+            ("4d6kh", "4d6kh1"),
+            ("1d20cs19cf<=2", "1d20cs=19cf<=2"),
+            ("3d6!", "3d6!"),
+            ("3d6!>4", "3d6!>4"),
+            ("adv", "adv"),
+            ("dis cs>=19 + 3", "dis cs>=19 + 3"),
+            ("adv!cf<3", "adv !cf<3"),
+            ("-(adv)", "-adv"),
+            ("(adv)d(dis)", "advddis"),
+            ("2d(adv)kh1", "2d(adv)kh1"),
+            ("2d(-dis!)!", "2d(-dis !)!"),
+            ("2 * -d6", "2 * -1d6"),
+            ("-d6kh1 + d4", "-1d6kh1 + 1d4"),
+        ];
+
+        for (input, output) in test_values {
+            let expr = Parser::new(input).parse().unwrap();
+            assert_eq!(expr.to_string(), output, "printing {input}");
+        }
+    }
+
+    fn source() -> impl Strategy<Value = String> {
+        let leaf = prop_oneof![
+            (0..7u8).prop_map(|n| n.to_string()),
+            Just("adv".to_string()),
+            Just("dis".to_string()),
+            Just("d6".to_string()),
+        ];
+        leaf.prop_recursive(4, 32, 2, |inner| {
+            let operator = prop_oneof![
+                Just("+"),
+                Just("-"),
+                Just("−"),
+                Just("*"),
+                Just("x"),
+                Just("×"),
+                Just("/"),
+                Just("÷"),
+            ];
+            let modifier = prop_oneof![
+                Just(""),
+                Just("kh2"),
+                Just("kl"),
+                Just("!"),
+                Just("!>=5"),
+                Just("cs>5"),
+                Just("cf<2"),
+            ];
+            prop_oneof![
+                (inner.clone(), operator.clone(), inner.clone())
+                    .prop_map(|(left, op, right)| format!("{left} {op} {right}")),
+                (inner.clone(), operator, inner.clone())
+                    .prop_map(|(left, op, right)| format!("({left}) {op} ({right})")),
+                inner.clone().prop_map(|right| format!("-{right}")),
+                inner.clone().prop_map(|expr| format!("({expr})")),
+                (inner.clone(), inner, modifier).prop_map(|(quantity, faces, modifier)| {
+                    format!("({quantity})d({faces}){modifier}")
+                }),
+            ]
+        })
+    }
+
+    /// The structure of an expression, without what the printer normalizes:
+    /// the spans, the parentheses and the spelling of the operators.
+    fn structure(expr: &Expr) -> String {
+        match expr {
+            Expr::Unary { operator, right } => {
+                format!("({} {})", symbol(operator.ty), structure(right))
+            }
+            Expr::Binary {
+                left,
+                operator,
+                right,
+            } => format!(
+                "({} {} {})",
+                symbol(operator.ty),
+                structure(left),
+                structure(right)
+            ),
+            Expr::Grouping { expression } => structure(expression),
+            Expr::Literal { value } => value.current.to_string(),
+            Expr::Roll {
+                quantity,
+                dice,
+                faces,
+                modifiers,
+            } => {
+                let modifiers: String = modifiers.iter().map(|m| m.to_string()).collect();
+                format!(
+                    "({} {} {} {modifiers})",
+                    dice.ty,
+                    structure(quantity),
+                    structure(faces)
+                )
+            }
+        }
+    }
+
+    #[test]
+    fn test_shorthand_extensions() {
+        // printing `adv` doesn't make it need the keep extension
+        let expr = Parser::new("adv + dis").parse().unwrap();
+        let printed = expr.to_string();
+        let reparsed = Parser::new(&printed).parse().unwrap();
+        let mut interpreter = Interpreter::builder()
+            .extensions(Extensions {
+                keep: false,
+                ..Extensions::all()
+            })
+            .build();
+        assert!(interpreter.interpret(&reparsed).is_ok(), "{printed}");
+    }
+
+    proptest! {
+        #[test]
+        fn parse_print(source in source(), seed: u64) {
+            let expr = Parser::new(&source).parse().unwrap();
+            let printed = expr.to_string();
+            let reparsed = Parser::new(&printed).parse().unwrap();
+            prop_assert_eq!(structure(&reparsed), structure(&expr));
+            prop_assert_eq!(reparsed.to_string(), printed.as_str());
+
+            // both expressions must roll the same dice in the same order
+            let mut left = Interpreter::builder().seed(seed).build();
+            let mut right = Interpreter::builder().seed(seed).build();
+            let left = left.interpret(&expr).map(|v| v.to_string()).map_err(|e| e.to_string());
+            let right = right.interpret(&reparsed).map(|v| v.to_string()).map_err(|e| e.to_string());
+            prop_assert_eq!(left, right);
+        }
+    }
+}
@@ -0,0 +1,63 @@
+use std::fmt::Write;
+
+use crate::{Evaluation, Expr, Format};
+
+impl<'e, 'a> Evaluation<'e, 'a> {
+    /// Every operation and roll of the evaluation, in the order the
+    /// interpreter computed them. The literals and groupings are skipped
+    /// since they don't compute anything.
+    pub fn steps(&self) -> Vec<&Evaluation<'e, 'a>> {
+        let mut steps = Vec::new();
+        self.push_steps(&mut steps);
+        steps
+    }
+
+    fn push_steps<'s>(&'s self, steps: &mut Vec<&'s Evaluation<'e, 'a>>) {
+        self.children
+            .iter()
+            .for_each(|child| child.push_steps(steps));
+        if !matches!(self.expr, Expr::Literal { .. } | Expr::Grouping { .. }) {
+            steps.push(self);
+        }
+    }
+
+    /// The [`Evaluation::steps`], one per line, like `2d6 = 7 [3, 4]`.
+    pub fn trace(&self, format: &impl Format) -> String {
+        let mut res = String::new();
+        for step in self.steps() {
+            write!(res, "{} = {}", step.expr, format.total(step.value.current)).unwrap();
+            if let Expr::Roll { .. } = step.expr {
+                let dice = step.value.dice();
+                let dice: Vec<_> = dice.iter().map(|die| format.die(die)).collect();
+                write!(res, " [{}]", dice.join(", ")).unwrap();
+            }
+            res.push('\n');
+        }
+        res
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Interpreter, Parser, Plain};
+
+    #[test]
+    fn test_trace() {
+        let expr = Parser::new("-(3d1 + 2) × 2d1kh1").parse().unwrap();
+        let evaluation = Interpreter::default().evaluate(&expr).unwrap();
+        assert_eq!(
+            evaluation.trace(&Plain),
+            "\
+3d1 = 3 [*1*, *1*, *1*]
+3d1 + 2 = 5
+-(3d1 + 2) = -5
+2d1kh1 = 1 [*1*, ~1~]
+-(3d1 + 2) * 2d1kh1 = -5
+"
+        );
+
+        let expr = Parser::new("42").parse().unwrap();
+        let evaluation = Interpreter::default().evaluate(&expr).unwrap();
+        assert!(evaluation.steps().is_empty());
+    }
+}
@@ -0,0 +1,161 @@
+//! This module is dedicated to the sources of the faces of the dice rolled
+//! by the [`crate::Interpreter`].
+use std::collections::VecDeque;
+
+use rand::Rng;
+
+/// Where the faces of the dice come from.
+///
+/// Every [`Rng`] is a source of fair dice, like [`rand::rngs::OsRng`] for the
+/// randomness of the operating system. [`Sequence`] and [`Scripted`] choose
+/// the faces instead, for the tests and the demonstrations.
+pub trait DiceSource {
+    /// The face of a die with `faces` faces, between 1 and `faces`.
+    fn roll(&mut self, faces: isize) -> isize;
+}
+
+/// The faces are drawn from [`rand::RngCore::next_u64`] by the same algorithm on
+/// every platform and with every version of `rand`, unlike
+/// [`Rng::gen_range`], so a seeded roll can be replayed anywhere.
+impl<R: Rng + ?Sized> DiceSource for R {
+    fn roll(&mut self, faces: isize) -> isize {
+        assert!(faces > 0, "a die needs at least one face");
+        // Lemire's widening multiplication, rejecting the values that would
+        // make the smallest faces more likely
+        let faces = faces as u64;
+        let threshold = faces.wrapping_neg() % faces;
+        loop {
+            let product = self.next_u64() as u128 * faces as u128;
+            if product as u64 >= threshold {
+                return (product >> 64) as isize + 1;
+            }
+        }
+    }
+}
+
+/// Recorded faces, returned in order whatever the dice are. These loaded
+/// dice make the result of an expression known in advance, for the tests,
+/// see [`crate::InterpreterBuilder::faces`].
+///
+/// # Panics
+///
+/// When rolling more dice than recorded, or when a face doesn't fit its
+/// die.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Sequence {
+    faces: VecDeque<isize>,
+}
+
+impl Sequence {
+    pub fn new(faces: impl IntoIterator<Item = isize>) -> Self {
+        Self {
+            faces: faces.into_iter().collect(),
+        }
+    }
+
+    /// The faces that weren't rolled yet.
+    pub fn remaining(&self) -> impl Iterator<Item = &isize> {
+        self.faces.iter()
+    }
+}
+
+impl DiceSource for Sequence {
+    fn roll(&mut self, faces: isize) -> isize {
+        let face = self
+            .faces
+            .pop_front()
+            .expect("rolled more dice than recorded");
+        assert!(
+            (1..=faces).contains(&face),
+            "the recorded face {face} doesn't fit a die with {faces} faces"
+        );
+        face
+    }
+}
+
+/// Forces the faces of the next dice, then rolls them with another source.
+/// The forced faces that don't fit their die are clamped, a forced 20 on a
+/// d6 is a 6.
+#[derive(Debug, Clone)]
+pub struct Scripted<D> {
+    forced: VecDeque<isize>,
+    source: D,
+}
+
+impl<D: DiceSource> Scripted<D> {
+    pub fn new(source: D) -> Self {
+        Self {
+            forced: VecDeque::new(),
+            source,
+        }
+    }
+
+    /// Force the face of the next die that isn't forced yet.
+    pub fn force(mut self, face: isize) -> Self {
+        self.forced.push_back(face);
+        self
+    }
+
+    pub fn source(&self) -> &D {
+        &self.source
+    }
+}
+
+impl<D: DiceSource> DiceSource for Scripted<D> {
+    fn roll(&mut self, faces: isize) -> isize {
+        match self.forced.pop_front() {
+            Some(face) => face.clamp(1, faces),
+            None => self.source.roll(faces),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Interpreter, Parser};
+
+    #[test]
+    fn test_portable() {
+        use rand::SeedableRng;
+
+        // these faces must never change, or the old logs can't be replayed
+        let mut rng = crate::SeededRng::seed_from_u64(42);
+        let faces: Vec<_> = [20, 20, 20, 6, 6, 6, 100, 2, 1, 1_000_000]
+            .into_iter()
+            .map(|faces| rng.roll(faces))
+            .collect();
+        assert_eq!(faces, [14, 20, 9, 4, 2, 1, 31, 2, 1, 238586]);
+
+        let mut rng = crate::FairRng::new(crate::ServerSeed([7; 32]), "players");
+        let faces: Vec<_> = (0..10).map(|_| rng.roll(20)).collect();
+        assert_eq!(faces, [8, 18, 9, 17, 20, 11, 17, 1, 13, 13]);
+        assert_eq!(rng.counter(), 20);
+    }
+
+    #[test]
+    fn test_sequence() -> crate::Result<()> {
+        let expr = Parser::new("3d6").parse()?;
+        let value = Interpreter::new(Sequence::new([3, 5, 6])).interpret(&expr)?;
+        assert_eq!(value.to_string(), "14 <= (14 (3 + 5 + *6*))");
+        Ok(())
+    }
+
+    #[test]
+    #[should_panic(expected = "rolled more dice than recorded")]
+    fn test_sequence_exhausted() {
+        let expr = Parser::new("3d6").parse().unwrap();
+        let _ = Interpreter::new(Sequence::new([3, 5])).interpret(&expr);
+    }
+
+    #[test]
+    fn test_scripted() -> crate::Result<()> {
+        let expr = Parser::new("1d6 + adv").parse()?;
+        let source = Scripted::new(Sequence::new([4])).force(20).force(20);
+        let mut interpreter = Interpreter::new(source);
+        let value = interpreter.interpret(&expr)?;
+        assert_eq!(value.to_string(), "26 <= (6 (*6*) + 20 (*20* + ~4~))");
+        assert_eq!(interpreter.rng().source().remaining().count(), 0);
+        Ok(())
+    }
+}
@@ -0,0 +1,429 @@
+//! The interactive prompt, started when stdin is a terminal.
+use std::{borrow::Cow, fmt::Write, path::PathBuf};
+
+use clap::ValueEnum;
+use dicey::*;
+use logos::Logos;
+use rustyline::{
+    completion::Completer,
+    error::ReadlineError,
+    highlight::Highlighter,
+    hint::Hinter,
+    validate::{ValidationContext, ValidationResult, Validator},
+    Editor, Helper,
+};
+
+use crate::{report, run, Cli, ResultFormat, Session};
+
+/// Edits the lines of the prompt.
+struct ReplHelper {
+    /// Whether the tokens are highlighted while typing.
+    color: bool,
+}
+
+impl Helper for ReplHelper {}
+
+/// The words completed at the start of the prompt.
+const COMMANDS: [&str; 6] = [":help", ":last", ":stats", ":format", ":seed", ":quit"];
+/// The words completed right after a number.
+const MODIFIERS: [&str; 4] = ["kh", "kl", "cs", "cf"];
+/// The words completed anywhere else.
+const SHORTHANDS: [&str; 2] = ["adv", "dis"];
+
+impl Completer for ReplHelper {
+    type Candidate = String;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _ctx: &rustyline::Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<String>)> {
+        Ok(complete(&line[..pos]))
+    }
+}
+
+/// The start of the word ending the line and the words it could be completed
+/// into.
+fn complete(line: &str) -> (usize, Vec<String>) {
+    let trimmed = line.trim_start();
+    if trimmed.starts_with(':') && !trimmed.contains(char::is_whitespace) {
+        return (line.len() - trimmed.len(), matching(&COMMANDS, trimmed));
+    }
+
+    let start = line
+        .rfind(|c: char| !c.is_ascii_alphabetic())
+        .map_or(0, |i| i + 1);
+    let (before, word) = line.split_at(start);
+    let candidates: &[&str] = if word.is_empty() || trimmed.starts_with(':') {
+        &[]
+    } else if before.ends_with(|c: char| c.is_ascii_digit()) {
+        &MODIFIERS
+    } else {
+        &SHORTHANDS
+    };
+    (start, matching(candidates, word))
+}
+
+fn matching(candidates: &[&str], prefix: &str) -> Vec<String> {
+    candidates
+        .iter()
+        .filter(|candidate| candidate.starts_with(prefix))
+        .map(|candidate| candidate.to_string())
+        .collect()
+}
+
+impl Hinter for ReplHelper {
+    type Hint = String;
+}
+
+impl Highlighter for ReplHelper {
+    fn highlight<'l>(&self, line: &'l str, _pos: usize) -> Cow<'l, str> {
+        if self.color && !line.trim_start().starts_with(':') {
+            Cow::Owned(highlight(line))
+        } else {
+            Cow::Borrowed(line)
+        }
+    }
+
+    fn highlight_char(&self, _line: &str, _pos: usize) -> bool {
+        self.color
+    }
+}
+
+/// Color the tokens of the line with ANSI escape codes. The dice and their
+/// modifiers are yellow, the operators dimmed, and what can't be lexed red.
+fn highlight(line: &str) -> String {
+    let mut res = String::with_capacity(line.len());
+    let mut end = 0;
+    for (ty, span) in TokenType::lexer(line).spanned() {
+        res.push_str(&line[end..span.start]);
+        let lexeme = &line[span.clone()];
+        end = span.end;
+        let color = match ty {
+            TokenType::Number | TokenType::Float => {
+                res.push_str(lexeme);
+                continue;
+            }
+            TokenType::Dice
+            | TokenType::CriticalSuccess
+            | TokenType::CriticalFailure
+            | TokenType::KeepHighest
+            | TokenType::KeepLowest
+            | TokenType::Explode
+            | TokenType::Advantage
+            | TokenType::Disadvantage => "33",
+            TokenType::Error => "31",
+            _ => "2",
+        };
+        write!(res, "\x1b[{color}m{lexeme}\x1b[0m").unwrap();
+    }
+    res.push_str(&line[end..]);
+    res
+}
+
+impl Validator for ReplHelper {
+    /// Keep reading lines while there are unclosed parentheses.
+    fn validate(&self, ctx: &mut ValidationContext) -> rustyline::Result<ValidationResult> {
+        if depth(ctx.input()) > 0 {
+            Ok(ValidationResult::Incomplete)
+        } else {
+            Ok(ValidationResult::Valid(None))
+        }
+    }
+}
+
+/// The number of parentheses left open at the end of the source.
+fn depth(source: &str) -> usize {
+    let mut depth = 0usize;
+    for ty in TokenType::lexer(source) {
+        match ty {
+            TokenType::LeftParen => depth += 1,
+            TokenType::RightParen => depth = depth.saturating_sub(1),
+            _ => (),
+        }
+    }
+    depth
+}
+
+/// Where the history is kept between sessions, if there's a data directory.
+fn history_path() -> Option<PathBuf> {
+    let dir = dirs::data_dir()?.join("dicey");
+    std::fs::create_dir_all(&dir).ok()?;
+    Some(dir.join("history"))
+}
+
+const HELP: &str = "\
+Enter an expression to roll it, like `4d6kh3 + 2`.
+
+  NdM        roll N dice with M faces, N defaults to 1
+  + - * /    arithmetic, `x`, `×` and `÷` work too
+  adv, dis   roll 2d20 and keep the highest or the lowest
+  kh N, kl N keep the N highest or lowest dice
+  ! [>=N]    explode the max faces, or the faces matching the condition
+  cs >=N     critical successes, the max face by default
+  cf <=N     critical failures, the 1 by default
+
+Commands:
+  :help            show this help
+  :last            roll the last expression again
+  :stats [N]       roll the last expression N times and show its distribution
+  :format [FORMAT] show or change the format of the results
+  :seed [N]        show or change the seed
+  :quit            leave the prompt
+
+There are no variables or macros to list, the language has none yet.
+";
+
+/// The number of samples of `:stats` by default.
+const SAMPLES: usize = 10_000;
+/// The most samples `:stats` takes, more would hang the prompt.
+const MAX_SAMPLES: usize = 1_000_000;
+
+/// A line of the prompt starting with a `:`.
+#[derive(Debug, PartialEq, Eq)]
+enum Command<'a> {
+    Help,
+    Last,
+    Stats(Option<&'a str>),
+    Format(Option<&'a str>),
+    Seed(Option<&'a str>),
+    Quit,
+}
+
+impl<'a> Command<'a> {
+    /// `None` if the line isn't a command, the name of the command if it's
+    /// unknown.
+    fn parse(line: &'a str) -> Option<std::result::Result<Self, &'a str>> {
+        let mut words = line.trim().strip_prefix(':')?.split_whitespace();
+        let name = words.next().unwrap_or_default();
+        let argument = words.next();
+        Some(Ok(match name {
+            "help" | "h" | "?" => Command::Help,
+            "last" | "l" => Command::Last,
+            "stats" => Command::Stats(argument),
+            "format" => Command::Format(argument),
+            "seed" => Command::Seed(argument),
+            "quit" | "q" | "exit" => Command::Quit,
+            _ => return Some(Err(name)),
+        }))
+    }
+}
+
+/// The state of the prompt, kept between two lines.
+struct Repl {
+    cli: Cli,
+    session: Session,
+    /// The last expression entered.
+    last: Option<String>,
+}
+
+impl Repl {
+    /// Evaluate a line and print its result, or the error. Return `false`
+    /// when the prompt should stop.
+    fn line(&mut self, line: &str) -> bool {
+        let command = match Command::parse(line) {
+            None => {
+                if self.roll(line) {
+                    self.last = Some(line.to_string());
+                }
+                return true;
+            }
+            Some(Err(name)) => {
+                println!("Unknown command `:{name}`, try `:help`.");
+                return true;
+            }
+            Some(Ok(command)) => command,
+        };
+
+        match command {
+            Command::Help => print!("{HELP}"),
+            Command::Last => match self.last.clone() {
+                Some(last) => {
+                    self.roll(&last);
+                }
+                None => println!("There is no expression to roll yet."),
+            },
+            Command::Stats(samples) => self.stats(samples),
+            Command::Format(None) => println!("{:?}", self.cli.result_format()),
+            Command::Format(Some(format)) => match ResultFormat::from_str(format, true) {
+                Ok(format) => {
+                    self.cli.json = false;
+                    self.cli.format = Some(format);
+                }
+                Err(e) => println!("Invalid format `{format}`: {e}"),
+            },
+            Command::Seed(_) if self.session.seed().is_none() => {
+                println!("The dice of this session aren't seeded.")
+            }
+            Command::Seed(None) => println!("{}", self.session.seed().unwrap_or_default()),
+            Command::Seed(Some(seed)) => match seed.parse() {
+                Ok(seed) => self.session.reseed(seed),
+                Err(e) => println!("Invalid seed `{seed}`: {e}"),
+            },
+            Command::Quit => return false,
+        }
+        true
+    }
+
+    /// Roll the source and print its result, or the error. Return whether
+    /// the source parsed.
+    fn roll(&mut self, source: &str) -> bool {
+        match run(&self.cli, &mut self.session, source) {
+            Ok(()) => true,
+            Err(error) => {
+                let parsed = !matches!(error, Error::Parser(_));
+                println!("{}", report(error, source));
+                parsed
+            }
+        }
+    }
+
+    /// Print the distribution of the last expression. The dice are rolled by
+    /// another interpreter so the seeded rolls of the session don't change.
+    fn stats(&self, samples: Option<&str>) {
+        let samples = match samples.map(str::parse).unwrap_or(Ok(SAMPLES)) {
+            Ok(samples) if samples <= MAX_SAMPLES => samples,
+            Ok(_) => return println!("Invalid number of samples: the most is {MAX_SAMPLES}"),
+            Err(e) => return println!("Invalid number of samples: {e}"),
+        };
+        let last = match &self.last {
+            Some(last) => last,
+            None => return println!("There is no expression to roll yet."),
+        };
+        match distribution(last, *self.session.interpreter.config(), samples) {
+            Ok(distribution) => print!("{distribution}"),
+            Err(error) => println!("{}", report(error, last)),
+        }
+    }
+}
+
+fn distribution(source: &str, config: Config, samples: usize) -> Result<Distribution> {
+    let expr = Parser::new(source).parse()?;
+    let mut interpreter = Interpreter::builder()
+        .config(config)
+        .verbosity(Verbosity::Total)
+        .build();
+    Ok(interpreter.distribution(&expr, samples)?)
+}
+
+pub fn run_prompt(cli: &Cli, session: Session) -> Result<()> {
+    let mut editor = Editor::<ReplHelper>::new();
+    editor.set_helper(Some(ReplHelper { color: cli.color() }));
+    let history = history_path();
+    if let Some(history) = &history {
+        // there's no history yet on the first run
+        let _ = editor.load_history(history);
+    }
+
+    let mut repl = Repl {
+        cli: cli.clone(),
+        session,
+        last: None,
+    };
+    loop {
+        let line = match editor.readline("> ") {
+            Ok(line) => line,
+            Err(ReadlineError::Interrupted) => continue,
+            Err(ReadlineError::Eof) => break,
+            Err(error) => return Err(anyhow::Error::from(error).into()),
+        };
+        if line.trim().is_empty() {
+            continue;
+        }
+        editor.add_history_entry(line.as_str());
+        if let Some(history) = &history {
+            if let Err(error) = editor.save_history(history) {
+                eprintln!("Cannot save the history: {error}");
+            }
+        }
+
+        if !repl.line(&line) {
+            break;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::SessionRng;
+
+    #[test]
+    fn test_depth() {
+        assert_eq!(depth("(1 + (2d6"), 2);
+        assert_eq!(depth("(1 + 2)\n* 3"), 0);
+        assert_eq!(depth(")("), 1);
+    }
+
+    #[test]
+    fn test_command() {
+        assert_eq!(Command::parse("2d6 + 1"), None);
+        assert_eq!(Command::parse(" :q "), Some(Ok(Command::Quit)));
+        assert_eq!(
+            Command::parse(":stats 100"),
+            Some(Ok(Command::Stats(Some("100"))))
+        );
+        assert_eq!(Command::parse(":seed"), Some(Ok(Command::Seed(None))));
+        assert_eq!(Command::parse(":vars"), Some(Err("vars")));
+        assert_eq!(Command::parse(":roll 2d6"), Some(Err("roll")));
+    }
+
+    #[test]
+    fn test_last() {
+        use clap::Parser as _;
+
+        let mut repl = Repl {
+            cli: Cli::parse_from(["dicey", "--color", "never"]),
+            session: Session::new(SessionRng::seeded(1), &[], Config::default(), None),
+            last: None,
+        };
+        assert!(repl.line("1d6 + 1"));
+        assert_eq!(repl.last.as_deref(), Some("1d6 + 1"));
+        // only the expressions that parse are rolled again
+        assert!(repl.line("1d6 +"));
+        assert_eq!(repl.last.as_deref(), Some("1d6 + 1"));
+        assert!(repl.line("1d0"));
+        assert_eq!(repl.last.as_deref(), Some("1d0"));
+        assert!(!repl.line(":quit"));
+    }
+
+    #[test]
+    fn test_stats() {
+        use clap::Parser as _;
+
+        let mut repl = Repl {
+            cli: Cli::parse_from(["dicey", "--color", "never"]),
+            session: Session::new(SessionRng::seeded(1), &[], Config::default(), None),
+            last: Some("1d6".to_string()),
+        };
+        // too many samples are refused instead of hanging the prompt
+        assert!(repl.line(&format!(":stats {}", usize::MAX)));
+        assert!(repl.line(":stats 10"));
+    }
+
+    #[test]
+    fn test_complete() {
+        let complete = |line| {
+            let (start, candidates) = complete(line);
+            (start, candidates.join(" "))
+        };
+        assert_eq!(complete("4d6k"), (3, "kh kl".to_string()));
+        assert_eq!(complete("1d20c"), (4, "cs cf".to_string()));
+        assert_eq!(complete("2 + a"), (4, "adv".to_string()));
+        assert_eq!(complete("4d"), (1, String::new()));
+        assert_eq!(complete("4d6 "), (4, String::new()));
+        assert_eq!(complete(" :s"), (1, ":stats :seed".to_string()));
+    }
+
+    #[test]
+    fn test_highlight() {
+        assert_eq!(
+            highlight("2d6kh1 + ?"),
+            "2\x1b[33md\x1b[0m6\x1b[33mkh\x1b[0m1 \x1b[2m+\x1b[0m \x1b[31m?\x1b[0m"
+        );
+        assert_eq!(highlight(" 42 "), " 42 ");
+    }
+}
@@ -0,0 +1,125 @@
+//! The state shared by every expression evaluated from the command line.
+use std::{fs::File, io::Write};
+
+use dicey::*;
+use rand::{rngs::OsRng, RngCore, SeedableRng};
+
+/// The generators the command line can roll with.
+pub enum SessionRng {
+    /// Seeded with `--seed`, or a random seed.
+    Seeded { seed: u64, rng: Box<SeededRng> },
+    /// Derived from `--server-seed` and `--client-seed`.
+    Fair(FairRng),
+    /// The randomness of the operating system, with `--os-random`.
+    Os(OsRng),
+}
+
+impl SessionRng {
+    pub fn seeded(seed: u64) -> Self {
+        SessionRng::Seeded {
+            seed,
+            rng: Box::new(SeededRng::seed_from_u64(seed)),
+        }
+    }
+}
+
+impl RngCore for SessionRng {
+    fn next_u32(&mut self) -> u32 {
+        match self {
+            SessionRng::Seeded { rng, .. } => rng.next_u32(),
+            SessionRng::Fair(rng) => rng.next_u32(),
+            SessionRng::Os(rng) => rng.next_u32(),
+        }
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        match self {
+            SessionRng::Seeded { rng, .. } => rng.next_u64(),
+            SessionRng::Fair(rng) => rng.next_u64(),
+            SessionRng::Os(rng) => rng.next_u64(),
+        }
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        match self {
+            SessionRng::Seeded { rng, .. } => rng.fill_bytes(dest),
+            SessionRng::Fair(rng) => rng.fill_bytes(dest),
+            SessionRng::Os(rng) => rng.fill_bytes(dest),
+        }
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> std::result::Result<(), rand::Error> {
+        match self {
+            SessionRng::Seeded { rng, .. } => rng.try_fill_bytes(dest),
+            SessionRng::Fair(rng) => rng.try_fill_bytes(dest),
+            SessionRng::Os(rng) => rng.try_fill_bytes(dest),
+        }
+    }
+}
+
+/// The interpreter of the command line, along with the log recording its
+/// rolls, if any. The faces given to `--force` come first.
+pub struct Session {
+    pub interpreter: Interpreter<Scripted<SessionRng>>,
+    log: Option<File>,
+}
+
+impl Session {
+    pub fn new(rng: SessionRng, forced: &[isize], config: Config, log: Option<File>) -> Self {
+        let rng = forced
+            .iter()
+            .fold(Scripted::new(rng), |rng, face| rng.force(*face));
+        Self {
+            interpreter: Interpreter::builder().rng(rng).config(config).build(),
+            log,
+        }
+    }
+
+    /// The seed of the session, if it's seeded.
+    pub fn seed(&self) -> Option<u64> {
+        match self.interpreter.rng().source() {
+            SessionRng::Seeded { seed, .. } => Some(*seed),
+            SessionRng::Fair(_) | SessionRng::Os(_) => None,
+        }
+    }
+
+    /// Start rolling from another seed, with the same configuration.
+    pub fn reseed(&mut self, seed: u64) {
+        let config = *self.interpreter.config();
+        self.interpreter = Interpreter::builder()
+            .rng(Scripted::new(SessionRng::seeded(seed)))
+            .config(config)
+            .build();
+    }
+
+    /// Evaluate the expression parsed from `source`, and append it to the
+    /// log. The rolls of the operating system aren't logged since they can't
+    /// be replayed.
+    pub fn evaluate<'e, 'a>(
+        &mut self,
+        source: &str,
+        expr: &'e Expr<'a>,
+    ) -> Result<Evaluation<'e, 'a>> {
+        let position = match self.interpreter.rng().source() {
+            SessionRng::Seeded { seed, rng } => Some(Position::Seeded {
+                seed: *seed,
+                word_pos: rng.get_word_pos(),
+            }),
+            SessionRng::Fair(rng) => Some(Position::Fair {
+                client_seed: rng.client_seed().to_string(),
+                counter: rng.counter(),
+            }),
+            SessionRng::Os(_) => None,
+        };
+        let evaluation = self.interpreter.evaluate(expr)?;
+        if let (Some(log), Some(position)) = (&mut self.log, position) {
+            let config = *self.interpreter.config();
+            let entry = LogEntry::new(source, position, config, evaluation.value.clone());
+            let json = serde_json::to_string(&entry).map_err(anyhow::Error::from)?;
+            // a single write, so the lines of concurrent sessions don't mix
+            log.write_all(format!("{json}\n").as_bytes())
+                .map_err(SetupError::from)?;
+        }
+        Ok(evaluation)
+    }
+}
@@ -0,0 +1,153 @@
+//! This module is dedicated to the estimation of the distribution of an
+//! [`Expr`], by rolling it many times.
+use std::{collections::BTreeMap, fmt::Display};
+
+use crate::{DiceSource, Expr, Interpreter, InterpreterError};
+
+/// How many times each total came up when rolling an expression.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Distribution {
+    counts: BTreeMap<isize, usize>,
+    samples: usize,
+}
+
+impl Distribution {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add(&mut self, total: isize) {
+        *self.counts.entry(total).or_default() += 1;
+        self.samples += 1;
+    }
+
+    /// Every total and the number of times it came up, in increasing order.
+    pub fn counts(&self) -> &BTreeMap<isize, usize> {
+        &self.counts
+    }
+
+    pub fn samples(&self) -> usize {
+        self.samples
+    }
+
+    pub fn min(&self) -> Option<isize> {
+        self.counts.keys().next().copied()
+    }
+
+    pub fn max(&self) -> Option<isize> {
+        self.counts.keys().next_back().copied()
+    }
+
+    pub fn mean(&self) -> Option<f64> {
+        if self.samples == 0 {
+            return None;
+        }
+        let sum: f64 = self
+            .counts
+            .iter()
+            .map(|(total, count)| *total as f64 * *count as f64)
+            .sum();
+        Some(sum / self.samples as f64)
+    }
+
+    pub fn std_dev(&self) -> Option<f64> {
+        let mean = self.mean()?;
+        let variance: f64 = self
+            .counts
+            .iter()
+            .map(|(total, count)| (*total as f64 - mean).powi(2) * *count as f64)
+            .sum();
+        Some((variance / self.samples as f64).sqrt())
+    }
+
+    /// The share of the samples equal to `total`, between 0 and 1.
+    pub fn probability(&self, total: isize) -> f64 {
+        match self.counts.get(&total) {
+            Some(count) => *count as f64 / self.samples as f64,
+            None => 0.,
+        }
+    }
+}
+
+/// A summary followed by a histogram, with a line per total.
+impl Display for Distribution {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let (min, max, mean, std_dev) = match (self.min(), self.max(), self.mean(), self.std_dev())
+        {
+            (Some(min), Some(max), Some(mean), Some(std_dev)) => (min, max, mean, std_dev),
+            _ => return writeln!(f, "no samples"),
+        };
+        writeln!(
+            f,
+            "{} samples, min {min}, max {max}, mean {mean:.2}, std dev {std_dev:.2}",
+            self.samples
+        )?;
+
+        let width = min.to_string().len().max(max.to_string().len());
+        let highest = self.counts.values().max().copied().unwrap_or(1);
+        for (total, count) in &self.counts {
+            let bar = "#".repeat((count * 40).div_ceil(highest));
+            let percent = self.probability(*total) * 100.;
+            writeln!(f, "{total:>width$} {percent:>6.2}% {bar}")?;
+        }
+        Ok(())
+    }
+}
+
+impl<R: DiceSource> Interpreter<R> {
+    /// Estimate the distribution of `expression` by interpreting it
+    /// `samples` times.
+    pub fn distribution(
+        &mut self,
+        expression: &Expr<'_>,
+        samples: usize,
+    ) -> Result<Distribution, InterpreterError> {
+        let mut distribution = Distribution::new();
+        for _ in 0..samples {
+            distribution.add(self.interpret(expression)?.current);
+        }
+        Ok(distribution)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Parser;
+
+    #[test]
+    fn test_distribution() -> crate::Result<()> {
+        let expr = Parser::new("1d6 + 2").parse()?;
+        let distribution = Interpreter::builder()
+            .seed(42)
+            .build()
+            .distribution(&expr, 6000)?;
+        assert_eq!(distribution.samples(), 6000);
+        assert_eq!(distribution.min(), Some(3));
+        assert_eq!(distribution.max(), Some(8));
+        assert_eq!(distribution.counts().len(), 6);
+        let mean = distribution.mean().unwrap();
+        assert!((mean - 5.5).abs() < 0.1, "{mean}");
+        for total in 3..=8 {
+            let probability = distribution.probability(total);
+            assert!(
+                (probability - 1. / 6.).abs() < 0.02,
+                "{total}: {probability}"
+            );
+        }
+
+        let expr = Parser::new("2d1 * 3").parse()?;
+        let distribution = Interpreter::default().distribution(&expr, 10)?;
+        assert_eq!(distribution.probability(6), 1.);
+        assert_eq!(distribution.std_dev(), Some(0.));
+        assert_eq!(
+            distribution.to_string(),
+            "\
+10 samples, min 6, max 6, mean 6.00, std dev 0.00
+6 100.00% ########################################
+"
+        );
+        assert_eq!(Distribution::new().to_string(), "no samples\n");
+        Ok(())
+    }
+}
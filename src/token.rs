@@ -1,11 +1,14 @@
 //! This module is dedicated to the definition and parsing of the [`Token`]s
-use std::fmt::Display;
+use std::{borrow::Cow, fmt::Display};
 
 use logos::{Lexer, Logos};
+use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Clone, PartialEq)]
+/// The lexeme of a token borrows the source it was parsed from, use
+/// [`Token::into_owned`] to get rid of this lifetime.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Token<'source> {
-    source: &'source str,
+    lexeme: Cow<'source, str>,
     pub span: logos::Span,
     pub ty: TokenType,
 }
@@ -13,26 +16,36 @@ pub struct Token<'source> {
 impl<'source> Token<'source> {
     pub fn new_from_lexer(lexer: &mut Lexer<'source, TokenType>) -> Self {
         let source = lexer.source();
-        if let Some(token_type) = lexer.next() {
-            Self {
-                source,
-                span: lexer.span(),
-                ty: token_type,
-            }
-        } else {
-            Self {
-                source,
-                span: source.len().saturating_sub(1)..source.len(),
-                ty: TokenType::EoF,
-            }
+        let (span, ty) = match lexer.next() {
+            Some(token_type) => (lexer.span(), token_type),
+            // the EoF token points to the last character of the source
+            None => match source.char_indices().next_back() {
+                Some((last, _)) => (last..source.len(), TokenType::EoF),
+                None => (0..0, TokenType::EoF),
+            },
+        };
+        Self {
+            lexeme: Cow::Borrowed(&source[span.clone()]),
+            span,
+            ty,
         }
     }
+
     pub fn lexeme(&self) -> &str {
-        &self.source[self.span.clone()]
+        &self.lexeme
+    }
+
+    pub fn into_owned(self) -> Token<'static> {
+        Token {
+            lexeme: Cow::Owned(self.lexeme.into_owned()),
+            span: self.span,
+            ty: self.ty,
+        }
     }
 }
 
-#[derive(Logos, Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Logos, Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum TokenType {
     // single character token
     #[token("(")]
@@ -52,6 +65,36 @@ pub enum TokenType {
     #[regex("[xX×]")]
     Multiplication,
 
+    // comparison
+    #[token("=")]
+    Equal,
+    #[token(">")]
+    Greater,
+    #[token(">=")]
+    GreaterEqual,
+    #[token("<")]
+    Less,
+    #[token("<=")]
+    LessEqual,
+
+    // roll modifiers
+    #[token("cs")]
+    CriticalSuccess,
+    #[token("cf")]
+    CriticalFailure,
+    #[token("kh")]
+    KeepHighest,
+    #[token("kl")]
+    KeepLowest,
+    #[token("!")]
+    Explode,
+
+    // d20 shorthands
+    #[token("adv")]
+    Advantage,
+    #[token("dis")]
+    Disadvantage,
+
     // Literals
     #[regex(r#"[0-9]+"#)]
     Number,
@@ -78,6 +121,18 @@ impl Display for TokenType {
             TokenType::Division => write!(f, "÷"),
             TokenType::Star => write!(f, "*"),
             TokenType::Multiplication => write!(f, "×"),
+            TokenType::Equal => write!(f, "="),
+            TokenType::Greater => write!(f, ">"),
+            TokenType::GreaterEqual => write!(f, ">="),
+            TokenType::Less => write!(f, "<"),
+            TokenType::LessEqual => write!(f, "<="),
+            TokenType::CriticalSuccess => write!(f, "cs"),
+            TokenType::CriticalFailure => write!(f, "cf"),
+            TokenType::KeepHighest => write!(f, "kh"),
+            TokenType::KeepLowest => write!(f, "kl"),
+            TokenType::Explode => write!(f, "!"),
+            TokenType::Advantage => write!(f, "adv"),
+            TokenType::Disadvantage => write!(f, "dis"),
             TokenType::Number => write!(f, "number"),
             TokenType::Float => write!(f, "float"),
             TokenType::Dice => write!(f, "dice"),
@@ -1,10 +1,12 @@
+use crate::Rounding;
+use serde::{Deserialize, Serialize};
 use std::{
     fmt::Display,
     iter::once,
     ops::{Add, Deref, Div, Mul, Neg, Sub},
 };
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Value {
     pub current: isize,
     pub all: Vec<Kind>,
@@ -16,13 +18,54 @@ impl PartialEq for Value {
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", content = "value", rename_all = "snake_case")]
 pub enum Kind {
     Direct(isize),
+    Die(Die),
     Roll(Vec<Kind>),
     Token(String),
 }
 
+/// A single die thrown during a roll.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Die {
+    pub value: isize,
+    pub faces: isize,
+    pub critical: Option<Critical>,
+    /// Dropped dice are shown in the breakdown but don't count in the total.
+    pub dropped: bool,
+    /// An exploded die is followed by the extra die it caused to be thrown.
+    pub exploded: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Critical {
+    Success,
+    Failure,
+}
+
+impl Die {
+    pub fn is_critical_success(&self) -> bool {
+        !self.dropped && self.critical == Some(Critical::Success)
+    }
+
+    pub fn is_critical_failure(&self) -> bool {
+        !self.dropped && self.critical == Some(Critical::Failure)
+    }
+}
+
+impl Kind {
+    fn dice<'a>(&'a self, dice: &mut Vec<&'a Die>) {
+        match self {
+            Kind::Die(die) => dice.push(die),
+            Kind::Roll(roll) => roll.iter().for_each(|kind| kind.dice(dice)),
+            Kind::Direct(_) | Kind::Token(_) => (),
+        }
+    }
+}
+
 impl Value {
     pub fn direct(inner: isize) -> Self {
         Self::new(inner, vec![Kind::Direct(inner)])
@@ -38,6 +81,21 @@ impl Value {
             all,
         }
     }
+
+    /// Every die thrown to compute this value, in order.
+    pub fn dice(&self) -> Vec<&Die> {
+        let mut dice = Vec::new();
+        self.all.iter().for_each(|kind| kind.dice(&mut dice));
+        dice
+    }
+
+    pub fn has_critical_success(&self) -> bool {
+        self.dice().iter().any(|die| die.is_critical_success())
+    }
+
+    pub fn has_critical_failure(&self) -> bool {
+        self.dice().iter().any(|die| die.is_critical_failure())
+    }
 }
 
 impl Display for Value {
@@ -47,23 +105,43 @@ impl Display for Value {
             .iter()
             .intersperse(&Kind::Token(" ".to_string()))
             .chain(once(&Kind::Token(")".to_string())))
-            .try_for_each(|kind| write!(f, "{kind}"))
+            .try_for_each(|kind| kind.fmt(f))
     }
 }
 
+/// Critical dice are highlighted between markers like `*20*` or `_1_` and
+/// dropped dice are struck through like `~3~`. Exploded dice are followed
+/// by a bang like `6!`. See [`crate::Format`] for richer outputs.
 impl Display for Kind {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             Kind::Direct(i) => write!(f, "{i}"),
+            Kind::Die(die) => die.fmt(f),
             Kind::Roll(roll) => once(&Kind::Token("(".to_string()))
-                .chain(roll.into_iter().intersperse(&Kind::Token(" ".to_string())))
+                .chain(roll.iter().intersperse(&Kind::Token(" ".to_string())))
                 .chain(once(&Kind::Token(")".to_string())))
-                .try_for_each(|kind| write!(f, "{kind}")),
+                .try_for_each(|kind| kind.fmt(f)),
             Kind::Token(s) => write!(f, "{s}"),
         }
     }
 }
 
+impl Display for Die {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let value = self.value;
+        match self.critical {
+            _ if self.dropped => write!(f, "~{value}~")?,
+            Some(Critical::Success) => write!(f, "*{value}*")?,
+            Some(Critical::Failure) => write!(f, "_{value}_")?,
+            None => write!(f, "{value}")?,
+        }
+        if self.exploded {
+            write!(f, "!")?;
+        }
+        Ok(())
+    }
+}
+
 impl PartialEq<isize> for Value {
     fn eq(&self, other: &isize) -> bool {
         self.current == *other
@@ -78,18 +156,66 @@ impl Deref for Value {
     }
 }
 
-impl Add for Value {
-    type Output = Value;
-
-    fn add(self, rhs: Self) -> Self::Output {
-        Self::new(
-            self.current + rhs.current,
+impl Value {
+    /// Join the breakdowns of the two values around `operator`, `None` when
+    /// the result overflowed.
+    fn combine(self, operator: &str, rhs: Self, result: Option<isize>) -> Option<Self> {
+        Some(Self::new(
+            result?,
             self.all
                 .into_iter()
-                .chain(once(Kind::Token("+".to_string())))
+                .chain(once(Kind::Token(operator.to_string())))
                 .chain(rhs.all)
                 .collect(),
-        )
+        ))
+    }
+
+    /// Add the two values, `None` when the result overflows.
+    pub fn checked_add(self, rhs: Self) -> Option<Self> {
+        let result = self.current.checked_add(rhs.current);
+        self.combine("+", rhs, result)
+    }
+
+    /// Subtract the two values, `None` when the result overflows.
+    pub fn checked_sub(self, rhs: Self) -> Option<Self> {
+        let result = self.current.checked_sub(rhs.current);
+        self.combine("-", rhs, result)
+    }
+
+    /// Multiply the two values, `None` when the result overflows.
+    pub fn checked_mul(self, rhs: Self) -> Option<Self> {
+        let result = self.current.checked_mul(rhs.current);
+        self.combine("x", rhs, result)
+    }
+
+    /// Divide the two values, rounding the result as asked. `None` when
+    /// `rhs` is zero or the result overflows.
+    pub fn checked_divide(self, rhs: Self, rounding: Rounding) -> Option<Self> {
+        let result = rounding.checked_divide(self.current, rhs.current);
+        self.combine("÷", rhs, result)
+    }
+
+    /// Negate the value, `None` when the result overflows.
+    pub fn checked_neg(self) -> Option<Self> {
+        self.current.checked_neg().map(Self::direct)
+    }
+
+    /// Divide the two values, rounding the result as asked.
+    ///
+    /// # Panics
+    ///
+    /// When `rhs` is zero or the result overflows.
+    pub fn divide(self, rhs: Self, rounding: Rounding) -> Self {
+        self.checked_divide(rhs, rounding)
+            .expect("attempt to divide by zero or with overflow")
+    }
+}
+
+impl Add for Value {
+    type Output = Value;
+
+    fn add(self, rhs: Self) -> Self::Output {
+        self.checked_add(rhs).expect("attempt to add with overflow")
     }
 }
 
@@ -97,28 +223,17 @@ impl Sub for Value {
     type Output = Value;
 
     fn sub(self, rhs: Self) -> Self::Output {
-        Self::new(
-            self.current - rhs.current,
-            self.all
-                .into_iter()
-                .chain(once(Kind::Token("-".to_string())))
-                .chain(rhs.all)
-                .collect(),
-        )
+        self.checked_sub(rhs)
+            .expect("attempt to subtract with overflow")
     }
 }
+
 impl Mul for Value {
     type Output = Value;
 
     fn mul(self, rhs: Self) -> Self::Output {
-        Self::new(
-            self.current * rhs.current,
-            self.all
-                .into_iter()
-                .chain(once(Kind::Token("x".to_string())))
-                .chain(rhs.all)
-                .collect(),
-        )
+        self.checked_mul(rhs)
+            .expect("attempt to multiply with overflow")
     }
 }
 
@@ -126,14 +241,7 @@ impl Div for Value {
     type Output = Value;
 
     fn div(self, rhs: Self) -> Self::Output {
-        Self::new(
-            self.current / rhs.current,
-            self.all
-                .into_iter()
-                .chain(once(Kind::Token("÷".to_string())))
-                .chain(rhs.all)
-                .collect(),
-        )
+        self.divide(rhs, Rounding::Truncate)
     }
 }
 
@@ -141,6 +249,51 @@ impl Neg for Value {
     type Output = Value;
 
     fn neg(self) -> Self::Output {
-        Self::direct(-self.current)
+        self.checked_neg().expect("attempt to negate with overflow")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_json() {
+        let die = |value, critical, dropped| {
+            Kind::Die(Die {
+                value,
+                faces: 20,
+                critical,
+                dropped,
+                exploded: false,
+            })
+        };
+        let roll = Kind::Roll(vec![
+            die(20, Some(Critical::Success), false),
+            Kind::Token("+".to_string()),
+            die(3, None, true),
+        ]);
+        let value = Value::new(20, vec![Kind::Direct(20), roll]) + Value::direct(2);
+
+        let json = serde_json::to_value(&value).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({
+                "current": 22,
+                "all": [
+                    { "type": "direct", "value": 20 },
+                    { "type": "roll", "value": [
+                        { "type": "die", "value": { "value": 20, "faces": 20, "critical": "success", "dropped": false, "exploded": false } },
+                        { "type": "token", "value": "+" },
+                        { "type": "die", "value": { "value": 3, "faces": 20, "critical": null, "dropped": true, "exploded": false } },
+                    ] },
+                    { "type": "token", "value": "+" },
+                    { "type": "direct", "value": 2 },
+                ],
+            })
+        );
+
+        let back: Value = serde_json::from_value(json).unwrap();
+        assert_eq!(back.to_string(), value.to_string());
     }
 }
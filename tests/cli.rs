@@ -0,0 +1,333 @@
+//! The exit statuses and the subcommands of the `dicey` binary.
+use std::{
+    fs,
+    io::Write,
+    path::PathBuf,
+    process::{Command, Output, Stdio},
+};
+
+use dicey::{LogEntry, Position, Value};
+
+/// Run the binary with `args`, writing `stdin` to its standard input.
+fn dicey(args: &[&str], stdin: &str) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_dicey"))
+        .args(["--color", "never"])
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(stdin.as_bytes())
+        .unwrap();
+    child.wait_with_output().unwrap()
+}
+
+fn stdout(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stdout).into_owned()
+}
+
+fn stderr(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stderr).into_owned()
+}
+
+/// An empty directory of its own for each test.
+fn directory(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("dicey-{}-{name}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+#[test]
+fn test_exit_status() {
+    let output = dicey(&["--seed", "1", "-e", "2 + 3", "-e", "1d1"], "");
+    assert_eq!(output.status.code(), Some(0), "{}", stderr(&output));
+    assert_eq!(stdout(&output), "5 <= (2 + 3)\n1 <= (1 (*1*))\n");
+
+    // an expression can start like an option
+    let output = dicey(&["-e", "-1 + 2", "-e", "-3"], "");
+    assert_eq!(output.status.code(), Some(0), "{}", stderr(&output));
+    assert_eq!(stdout(&output), "1 <= (-1 + 2)\n-3 <= (-3)\n");
+    let output = dicey(&["roll", "-1 + 2", "-d1"], "");
+    assert_eq!(output.status.code(), Some(0), "{}", stderr(&output));
+    assert_eq!(stdout(&output), "1 <= (-1 + 2)\n-1 <= (-1)\n");
+    let output = dicey(&["roll", "-2", "-q", "1d1"], "");
+    assert_eq!(stdout(&output), "-2\n1\n");
+
+    let output = dicey(&["-e", "1 +"], "");
+    assert_eq!(output.status.code(), Some(3));
+    assert!(stderr(&output).contains("Expected `number`"));
+
+    let output = dicey(&["roll", "1d0"], "");
+    assert_eq!(output.status.code(), Some(4));
+    assert!(stderr(&output).contains("Cannot roll a die with 0 faces"));
+
+    let output = dicey(&["-e", "1000000 * 1000000 * 1000000 * 1000000"], "");
+    assert_eq!(output.status.code(), Some(4));
+
+    let dir = directory("exit-status");
+    let missing = dir.join("missing");
+    let output = dicey(&["-f", missing.to_str().unwrap()], "");
+    assert_eq!(output.status.code(), Some(5));
+    assert!(stderr(&output).contains(missing.to_str().unwrap()));
+
+    // a script can have the name of a command
+    let script = dir.join("roll");
+    fs::write(&script, "2 + 3\n").unwrap();
+    let output = dicey(&["--file", script.to_str().unwrap()], "");
+    assert_eq!(output.status.code(), Some(0), "{}", stderr(&output));
+    assert_eq!(stdout(&output), "5 <= (2 + 3)\n");
+
+    let output = dicey(&["--seed", "nope"], "");
+    assert_eq!(output.status.code(), Some(2));
+
+    // the first error stops the evaluation
+    let output = dicey(&["-e", "1d0", "-e", "1 +", "-e", "2"], "");
+    assert_eq!(output.status.code(), Some(4));
+    assert_eq!(stdout(&output), "");
+}
+
+#[test]
+fn test_lines() {
+    let input = "1d1\n\n2 +\n1d0\n3\n";
+    let output = dicey(&[], input);
+    assert_eq!(output.status.code(), Some(3));
+    assert_eq!(stdout(&output), "1 <= (1 (*1*))\n");
+    assert!(stderr(&output).contains(" 3 │ 2 +"), "{}", stderr(&output));
+    assert!(!stderr(&output).contains("1d0"));
+
+    // the exit status is the one of the last error
+    let output = dicey(&["--continue-on-error"], input);
+    assert_eq!(output.status.code(), Some(4));
+    assert_eq!(stdout(&output), "1 <= (1 (*1*))\n3 <= (3)\n");
+    let stderr = stderr(&output);
+    assert!(stderr.contains(" 3 │ 2 +"), "{stderr}");
+    assert!(stderr.contains(" 4 │ 1d0"), "{stderr}");
+    // only the failing line is shown
+    assert!(!stderr.contains("1d1"), "{stderr}");
+
+    let output = dicey(&["--continue-on-error"], "1d0\n2\n");
+    assert_eq!(output.status.code(), Some(4));
+    let output = dicey(&["--continue-on-error", "-e", "1 +", "-e", "2"], "");
+    assert_eq!(output.status.code(), Some(3));
+    assert_eq!(stdout(&output), "2 <= (2)\n");
+}
+
+#[test]
+fn test_check() {
+    let dir = directory("check");
+    let (good, bad) = (dir.join("good.dice"), dir.join("bad.dice"));
+    fs::write(&good, "4d6kh3 + 2\n").unwrap();
+    fs::write(&bad, "4d6kh3 +\n").unwrap();
+
+    let output = dicey(
+        &["check", good.to_str().unwrap(), good.to_str().unwrap()],
+        "",
+    );
+    assert_eq!(output.status.code(), Some(0), "{}", stderr(&output));
+
+    // every script is checked, even after an error
+    let output = dicey(
+        &[
+            "check",
+            bad.to_str().unwrap(),
+            good.to_str().unwrap(),
+            bad.to_str().unwrap(),
+        ],
+        "",
+    );
+    assert_eq!(output.status.code(), Some(3));
+    assert_eq!(stderr(&output).matches("bad.dice: ").count(), 2);
+    assert!(!stderr(&output).contains("good.dice"));
+
+    let missing = dir.join("missing.dice");
+    let output = dicey(&["check", missing.to_str().unwrap()], "");
+    assert_eq!(output.status.code(), Some(5));
+}
+
+#[test]
+fn test_fmt() {
+    let dir = directory("fmt");
+    let script = dir.join("script.dice");
+    fs::write(&script, "((2)d6) × 3 + d20\n").unwrap();
+
+    let output = dicey(&["fmt", script.to_str().unwrap()], "");
+    assert_eq!(output.status.code(), Some(0), "{}", stderr(&output));
+    assert_eq!(stdout(&output), "2d6 * 3 + 1d20\n");
+    assert_eq!(fs::read_to_string(&script).unwrap(), "((2)d6) × 3 + d20\n");
+
+    let output = dicey(&["fmt", "--write", script.to_str().unwrap()], "");
+    assert_eq!(output.status.code(), Some(0), "{}", stderr(&output));
+    assert_eq!(stdout(&output), "");
+    assert_eq!(fs::read_to_string(&script).unwrap(), "2d6 * 3 + 1d20\n");
+
+    // a script that doesn't parse is left as it is
+    fs::write(&script, "2d6 *\n").unwrap();
+    let output = dicey(&["fmt", "-w", script.to_str().unwrap()], "");
+    assert_eq!(output.status.code(), Some(3));
+    assert_eq!(fs::read_to_string(&script).unwrap(), "2d6 *\n");
+}
+
+#[test]
+fn test_replay() {
+    let dir = directory("replay");
+    let log = dir.join("rolls.jsonl");
+    let log = log.to_str().unwrap();
+    let output = dicey(
+        &["--seed", "3", "--log", log, "-e", "2d6", "-e", "1d20!"],
+        "",
+    );
+    assert_eq!(output.status.code(), Some(0), "{}", stderr(&output));
+
+    let output = dicey(&["replay", log], "");
+    assert_eq!(output.status.code(), Some(0), "{}", stderr(&output));
+    assert_eq!(stdout(&output).matches("ok ").count(), 2);
+
+    // fudge the second roll
+    let mut entries: Vec<LogEntry> = fs::read_to_string(log)
+        .unwrap()
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    entries[1].result = Value::direct(entries[1].result.current + 1);
+    let lines: String = entries
+        .iter()
+        .map(|entry| serde_json::to_string(entry).unwrap() + "\n")
+        .collect();
+    fs::write(log, lines.clone()).unwrap();
+
+    let output = dicey(&["replay", log], "");
+    assert_eq!(output.status.code(), Some(6));
+    assert!(stdout(&output).starts_with("ok 1: 2d6"));
+    assert!(stdout(&output).contains("MISMATCH 2: 1d20!"));
+
+    // a malformed line doesn't stop the replay
+    fs::write(log, format!("{{\"source\":\n{lines}")).unwrap();
+    let output = dicey(&["replay", log], "");
+    assert_eq!(output.status.code(), Some(6), "{}", stderr(&output));
+    assert!(stdout(&output).starts_with("INVALID 1: "));
+    assert!(stdout(&output).contains("ok 2: 2d6"));
+    assert!(stdout(&output).contains("MISMATCH 3: 1d20!"));
+}
+
+#[test]
+fn test_verify_commitment() {
+    let output = dicey(&["commit"], "");
+    assert_eq!(output.status.code(), Some(0), "{}", stderr(&output));
+    let commit = stdout(&output);
+    let server_seed = commit
+        .lines()
+        .next()
+        .unwrap()
+        .split(' ')
+        .next_back()
+        .unwrap();
+    let commitment = commit
+        .lines()
+        .nth(1)
+        .unwrap()
+        .split(' ')
+        .next_back()
+        .unwrap();
+
+    let dir = directory("verify");
+    let log = dir.join("rolls.jsonl");
+    let log = log.to_str().unwrap();
+    let args = ["--server-seed", server_seed, "--client-seed", "players"];
+    let rolls = ["--log", log, "-e", "2d6", "-e", "1d20!"];
+    let output = dicey(&[&args[..], &rolls].concat(), "");
+    assert_eq!(output.status.code(), Some(0), "{}", stderr(&output));
+
+    let output = dicey(&[&args[..], &["verify", commitment, log]].concat(), "");
+    assert_eq!(output.status.code(), Some(0), "{}", stderr(&output));
+    assert!(stdout(&output).starts_with("ok 1: 2d6"));
+    assert!(stdout(&output).contains("ok 2: 1d20!"));
+    // the client seeds are in the log
+    let output = dicey(
+        &["--server-seed", server_seed, "verify", commitment, log],
+        "",
+    );
+    assert_eq!(output.status.code(), Some(0), "{}", stderr(&output));
+    assert_eq!(stdout(&output).matches("ok ").count(), 2);
+
+    let output = dicey(&[&args[..], &["verify", &"0".repeat(64), log]].concat(), "");
+    assert_eq!(output.status.code(), Some(6));
+    assert!(stderr(&output).contains("doesn't match the commitment"));
+
+    let other = ["--server-seed", server_seed, "--client-seed", "others"];
+    let output = dicey(&[&other[..], &["verify", commitment, log]].concat(), "");
+    assert_eq!(output.status.code(), Some(6));
+    assert!(stdout(&output).starts_with("MISMATCH 1: 2d6"));
+
+    let entries: Vec<LogEntry> = fs::read_to_string(log)
+        .unwrap()
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    let verify = |entries: &[LogEntry]| {
+        let lines: String = entries
+            .iter()
+            .map(|entry| serde_json::to_string(entry).unwrap() + "\n")
+            .collect();
+        fs::write(log, lines).unwrap();
+        dicey(&[&args[..], &["verify", commitment, log]].concat(), "")
+    };
+
+    // fudge the first roll
+    let mut fudged = entries.clone();
+    fudged[0].result = Value::direct(fudged[0].result.current + 1);
+    let output = verify(&fudged);
+    assert_eq!(output.status.code(), Some(6));
+    assert!(stdout(&output).starts_with("MISMATCH 1: 2d6"));
+    assert!(stdout(&output).contains("ok 2: 1d20!"));
+
+    // drop the first roll
+    let output = verify(&entries[1..]);
+    assert_eq!(output.status.code(), Some(6));
+    assert!(stdout(&output).starts_with("MISMATCH 1: 1d20! was rolled at the counter"));
+
+    // swap the rolls
+    let output = verify(&[entries[1].clone(), entries[0].clone()]);
+    assert_eq!(output.status.code(), Some(6));
+    assert!(stdout(&output).starts_with("MISMATCH 1: 1d20!"));
+    assert!(stdout(&output).contains("MISMATCH 2: 2d6"));
+
+    // the rolls of another session with another client seed
+    let mut mixed = entries.clone();
+    mixed[1].position = Position::Fair {
+        client_seed: "others".to_string(),
+        counter: 0,
+    };
+    let output = verify(&mixed);
+    assert_eq!(output.status.code(), Some(6));
+    assert!(stdout(&output).contains("MISMATCH 2: 1d20! was rolled with the client seed"));
+
+    // a seeded roll can be replayed, but isn't fair
+    let seeded = dir.join("seeded.jsonl");
+    let output = dicey(
+        &[
+            "--seed",
+            "3",
+            "--log",
+            seeded.to_str().unwrap(),
+            "-e",
+            "1d6",
+        ],
+        "",
+    );
+    assert_eq!(output.status.code(), Some(0), "{}", stderr(&output));
+    let seeded: LogEntry = serde_json::from_str(&fs::read_to_string(seeded).unwrap()).unwrap();
+    let output = verify(&[entries[0].clone(), seeded, entries[1].clone()]);
+    assert_eq!(output.status.code(), Some(6));
+    assert!(stdout(&output).contains("INVALID 2: 1d6 wasn't rolled with fair dice"));
+    assert!(stdout(&output).contains("ok 3: 1d20!"));
+
+    // rolling fair dice needs both seeds
+    let output = dicey(&["--server-seed", server_seed, "-e", "2d6"], "");
+    assert_eq!(output.status.code(), Some(2));
+}